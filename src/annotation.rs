@@ -0,0 +1,324 @@
+use crate::Format;
+use crate::compile::{Doc, Section};
+
+// Lines of code can be annotated with a numbered marker, explained by a
+// list at the start of the prose right after the code:
+//
+//     let mut my_fn_mut = || { // (1)
+//         ...
+//     }
+//
+//     //t (1) The closure must be declared as mutable to be able to mutate
+//     //t the captured values.
+//
+// Markers are numbered from 1 in order, and every marker must have its
+// explanation. Explanations are rendered as a numbered list in markdown,
+// and with numbered badges in HTML.
+
+// Splits the explanation lists out of the prose, checking them against
+// the markers of the code before.
+pub fn extract(sections: Vec<Section>, fp: &str) -> Result<Vec<Section>, String> {
+    let mut out = Vec::new();
+    // (number, line) of the markers of the last code section
+    let mut pending: Vec<(usize, usize)> = Vec::new();
+    for s in sections {
+        match s {
+            Section::Code {
+                start, ref lines, ..
+            } if lines.iter().any(|l| !l.trim().is_empty()) => {
+                unexplained(&pending, fp)?;
+                pending = markers(start, lines, fp)?;
+                out.push(s);
+            }
+            Section::Comment { start, lines } if !pending.is_empty() => {
+                let n = list_len(&lines);
+                check(&pending, start, &lines[..n], fp)?;
+                pending.clear();
+
+                let mut lines = lines;
+                let rest = lines.split_off(n);
+                out.push(Section::Annotations { start, lines });
+                let blank = rest.iter().take_while(|l| l.trim().is_empty()).count();
+                if blank < rest.len() {
+                    out.push(Section::Comment {
+                        start: start + n + blank,
+                        lines: rest[blank..].to_vec(),
+                    });
+                }
+            }
+            Section::Code { .. } | Section::Directive { .. } | Section::Callout { .. } => {
+                out.push(s)
+            }
+            s => {
+                unexplained(&pending, fp)?;
+                pending.clear();
+                out.push(s);
+            }
+        }
+    }
+
+    unexplained(&pending, fp)?;
+    Ok(out)
+}
+
+pub fn render(doc: &mut Doc, format: Format) {
+    for s in doc.sections_mut() {
+        let lines = match s {
+            Section::Annotations { lines, .. } => lines,
+            _ => continue,
+        };
+
+        let mut out = Vec::with_capacity(lines.len());
+        let mut indent = 0;
+        for l in lines.iter() {
+            if l.trim().is_empty() {
+                continue;
+            }
+            let (n, text) = match item(l) {
+                Some(item) => item,
+                None => {
+                    out.push(format!("{}{}", " ".repeat(indent), l.trim_start()));
+                    continue;
+                }
+            };
+            match format {
+                Format::Markdown => {
+                    let marker = format!("{}. ", n);
+                    indent = marker.len();
+                    out.push(format!("{}{}", marker, text));
+                }
+                // a paragraph each, so that inline markdown is rendered
+                Format::Mdbook => {
+                    if !out.is_empty() {
+                        out.push(String::new());
+                    }
+                    out.push(format!(
+                        "<span class=\"code-annotation\">{}</span> {}",
+                        n, text
+                    ));
+                }
+            }
+        }
+        *lines = out;
+    }
+}
+
+// Number of the `// (N)` marker ending a line of code, if any.
+pub fn marker(line: &str) -> Option<usize> {
+    let (code, comment) = line.rsplit_once("//")?;
+    if code.matches('"').count() % 2 == 1 {
+        return None;
+    }
+    number(comment.trim().strip_prefix('(')?.strip_suffix(')')?)
+}
+
+fn markers(start: usize, lines: &[String], fp: &str) -> Result<Vec<(usize, usize)>, String> {
+    let mut out = Vec::new();
+    for (i, l) in lines.iter().enumerate() {
+        let n = match marker(l) {
+            Some(n) => n,
+            None => continue,
+        };
+        if n != out.len() + 1 {
+            return Err(format!(
+                "{}:{}: marker ({}) out of order, expected ({})",
+                fp,
+                start + i,
+                n,
+                out.len() + 1
+            ));
+        }
+        out.push((n, start + i));
+    }
+    Ok(out)
+}
+
+fn check(
+    markers: &[(usize, usize)],
+    start: usize,
+    list: &[String],
+    fp: &str,
+) -> Result<(), String> {
+    let mut k = 0;
+    for (i, l) in list.iter().enumerate() {
+        let n = match item(l) {
+            Some((n, _)) => n,
+            None => continue,
+        };
+        match markers.get(k) {
+            Some((expected, _)) if *expected == n => k += 1,
+            Some((expected, _)) => {
+                return Err(format!(
+                    "{}:{}: explanation ({}) out of order, expected ({})",
+                    fp,
+                    start + i,
+                    n,
+                    expected
+                ));
+            }
+            None => {
+                return Err(format!(
+                    "{}:{}: explanation ({}) has no marker in the code",
+                    fp,
+                    start + i,
+                    n
+                ));
+            }
+        }
+    }
+    unexplained(&markers[k..], fp)
+}
+
+fn unexplained(markers: &[(usize, usize)], fp: &str) -> Result<(), String> {
+    match markers.first() {
+        Some((n, line)) => Err(format!(
+            "{}:{}: marker ({}) has no explanation",
+            fp, line, n
+        )),
+        None => Ok(()),
+    }
+}
+
+// Lines of the list at the start of the prose: the items and their
+// continuation lines, up to the first paragraph that isn't an item.
+fn list_len(lines: &[String]) -> usize {
+    let mut len = 0;
+    let mut in_list = false;
+    for (i, l) in lines.iter().enumerate() {
+        if l.trim().is_empty() {
+            continue;
+        }
+        let starts_item = item(l).is_some();
+        let after_blank = i > 0 && lines[i - 1].trim().is_empty();
+        if !starts_item && (!in_list || after_blank) {
+            break;
+        }
+        in_list = true;
+        len = i + 1;
+    }
+    len
+}
+
+// `(N) text` as (N, text).
+fn item(line: &str) -> Option<(usize, &str)> {
+    let rest = line.trim_start().strip_prefix('(')?;
+    let (n, text) = rest.split_once(") ")?;
+    Some((number(n)?, text.trim_start()))
+}
+
+fn number(s: &str) -> Option<usize> {
+    match !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        true => s.parse().ok(),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(src: &str) -> Vec<String> {
+        src.lines().map(String::from).collect()
+    }
+
+    fn code(start: usize, src: &str) -> Section {
+        Section::Code {
+            start,
+            lines: lines(src),
+            attrs: Default::default(),
+        }
+    }
+
+    fn comment(start: usize, src: &str) -> Section {
+        Section::Comment {
+            start,
+            lines: lines(src),
+        }
+    }
+
+    #[test]
+    fn markers() {
+        assert_eq!(marker("let x = 1; // (1)"), Some(1));
+        assert_eq!(marker("f(); //  (12) "), Some(12));
+        assert_eq!(marker("let s = \"// (1)\";"), None);
+        assert_eq!(marker("f(); // (a)"), None);
+        assert_eq!(marker("f(); // ()"), None);
+        assert_eq!(marker("f(); // see (1)"), None);
+    }
+
+    #[test]
+    fn list_at_the_start_of_the_prose() {
+        let sections = vec![
+            code(1, "let a = 1; // (1)\nlet b = 2;\nlet c = 3; // (2)"),
+            comment(5, "(1) First,\ncontinued.\n\n(2) Second.\n\nA paragraph."),
+        ];
+        let out = extract(sections, "x.rs").unwrap();
+        match &out[..] {
+            [
+                Section::Code { .. },
+                Section::Annotations {
+                    start: 5,
+                    lines: list,
+                },
+                Section::Comment {
+                    start: 10,
+                    lines: rest,
+                },
+            ] => {
+                assert_eq!(list, &lines("(1) First,\ncontinued.\n\n(2) Second."));
+                assert_eq!(rest, &lines("A paragraph."));
+            }
+            out => panic!("unexpected sections {:?}", out),
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for (sections, err) in [
+            (
+                vec![code(1, "a(); // (2)")],
+                "x.rs:1: marker (2) out of order, expected (1)",
+            ),
+            (
+                vec![code(1, "a(); // (1)\nb(); // (2)"), comment(4, "(1) A.")],
+                "x.rs:2: marker (2) has no explanation",
+            ),
+            (
+                vec![code(1, "a(); // (1)"), comment(3, "(1) A.\n(2) B.")],
+                "x.rs:4: explanation (2) has no marker in the code",
+            ),
+            (
+                vec![
+                    code(1, "a(); // (1)\nb(); // (2)"),
+                    comment(4, "(2) B.\n(1) A."),
+                ],
+                "x.rs:4: explanation (2) out of order, expected (1)",
+            ),
+            (
+                vec![code(1, "a(); // (1)"), code(3, "b();")],
+                "x.rs:1: marker (1) has no explanation",
+            ),
+        ] {
+            assert_eq!(extract(sections, "x.rs").unwrap_err(), err);
+        }
+    }
+
+    #[test]
+    fn rendering() {
+        let src = "fn a() {\n    let x = 1; // (1)\n}\n\n//t (1) One,\n//t   `two`.\n";
+        let render_with = |format| {
+            let mut doc = crate::compile::compile("x.rs", src).unwrap();
+            render(&mut doc, format);
+            doc.to_markdown()
+        };
+        let markdown = render_with(Format::Markdown);
+        let mdbook = render_with(Format::Mdbook);
+
+        assert!(markdown.contains("1. One,\n   `two`."), "{}", markdown);
+        assert!(
+            mdbook.contains("<span class=\"code-annotation\">1</span> One,\n`two`."),
+            "{}",
+            mdbook
+        );
+    }
+}
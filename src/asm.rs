@@ -0,0 +1,263 @@
+use crate::demangle;
+use crate::probe;
+use std::collections::HashMap;
+
+// The assembly of a function is requested with a top-level directive
+// naming it, with generic arguments if any:
+//
+//     //t!asm ex_use_notif::<WhatsappMessage>
+//
+// The chapter is compiled with optimizations, together with an exported
+// function taking the address of the requested one. This forces the
+// instantiation and leads to its (mangled) symbol in the assembly.
+//
+// Symbols, labels and syntax are those of x86-64 ELF targets: elsewhere
+// the directives are skipped, and the assembly is left out of the docs.
+pub const SUPPORTED: bool = cfg!(all(
+    target_arch = "x86_64",
+    target_family = "unix",
+    not(target_vendor = "apple")
+));
+const DIRECTIVE: &str = "//t!asm ";
+const WRAPPER: &str = "__handbook_asm_";
+
+pub struct Asm {
+    pub line: usize,
+    pub path: String,
+    pub lines: Vec<String>,
+}
+
+pub fn extract(fp: &str, src: &str) -> Result<Vec<Asm>, String> {
+    let mut paths: Vec<(usize, String)> = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        if let Some(path) = line.strip_prefix(DIRECTIVE) {
+            paths.push((i + 1, path.trim().to_string()));
+        }
+    }
+
+    if paths.is_empty() || !SUPPORTED {
+        return Ok(Vec::new());
+    }
+
+    let mut probe_src = src.to_string();
+    for (i, (_, path)) in paths.iter().enumerate() {
+        probe_src.push_str(&format!(
+            "\n#[unsafe(no_mangle)]\npub extern \"C\" fn {}{}() -> usize {{\n    {} as usize\n}}\n",
+            WRAPPER, i, path
+        ));
+    }
+
+    let crate_name = fp.rsplit('/').next().unwrap_or(fp).trim_end_matches(".rs");
+    let asm = probe::asm(&probe::name(fp, "asm"), &probe_src, crate_name)
+        .map_err(|e| format!("{}: {}", fp, e))?;
+    let asm: Vec<&str> = asm.lines().collect();
+
+    let mut out = Vec::new();
+    for (i, (line, path)) in paths.into_iter().enumerate() {
+        let not_found = || format!("{}:{}: no assembly found for `{}`", fp, line, path);
+
+        let wrapper = body(&asm, &format!("{}{}", WRAPPER, i)).ok_or_else(not_found)?;
+        let symbol = wrapper
+            .iter()
+            .find_map(|l| referenced_symbol(l))
+            .ok_or_else(not_found)?;
+        let lines = body(&asm, symbol).ok_or_else(not_found)?;
+
+        let mut lines: Vec<String> = lines
+            .into_iter()
+            .filter(|l| !is_noise(l))
+            .map(|l| {
+                let l = demangle::line(&l.trim().replace('\t', " "));
+                if l.ends_with(':') {
+                    l
+                } else {
+                    format!("    {}", l)
+                }
+            })
+            .collect();
+        // the symbol has no generic arguments
+        lines[0] = format!("{}::{}:", crate_name, path);
+
+        out.push(Asm {
+            line,
+            path,
+            lines: number_labels(lines),
+        });
+    }
+
+    Ok(out)
+}
+
+// Renders each function as a collapsible assembly block.
+pub fn render(asms: &[&Asm]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for a in asms {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+
+        let path = a.path.replace('<', "&lt;").replace('>', "&gt;");
+        lines.push("<details>".to_string());
+        lines.push(format!(
+            "<summary>Assembly of <code>{}</code></summary>",
+            path
+        ));
+        lines.push(String::new());
+        lines.push("```asm".to_string());
+        lines.extend(a.lines.iter().cloned());
+        lines.push("```".to_string());
+        lines.push(String::new());
+        lines.push("</details>".to_string());
+    }
+
+    lines
+}
+
+// Lines from the label of the symbol to the end of the function.
+fn body<'a>(asm: &[&'a str], symbol: &str) -> Option<Vec<&'a str>> {
+    let label = format!("{}:", symbol);
+    let start = asm.iter().position(|l| *l == label)?;
+
+    let mut lines = Vec::new();
+    for l in asm[start..].iter() {
+        if l.trim() == ".cfi_endproc" {
+            return Some(lines);
+        }
+        lines.push(*l);
+    }
+
+    None
+}
+
+fn referenced_symbol(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("[rip + ")?;
+    let symbol = rest.split([']', '@']).next()?;
+    Some(symbol.trim())
+}
+
+// Assembler directives and labels not targeted by any jump.
+fn is_noise(line: &str) -> bool {
+    let l = line.trim();
+    if l.is_empty() || l.starts_with('#') {
+        return true;
+    }
+    if l.ends_with(':') {
+        return l.starts_with(".Ltmp") || l.starts_with(".Lfunc");
+    }
+    l.starts_with('.')
+}
+
+// Local labels are named after the position of the function in the
+// output (`.LBB6_4`) or after a hash (`.Lanon.00d34e4c18d2944d.3`), so
+// they are numbered in order of appearance instead: `.LBB_1`, `.Lanon_1`.
+fn number_labels(lines: Vec<String>) -> Vec<String> {
+    let mut names: HashMap<String, String> = HashMap::new();
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut rename = |label: &str| -> String {
+        if let Some(name) = names.get(label) {
+            return name.clone();
+        }
+        let rest = &label[2..];
+        let kind = &label[..2 + rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len())];
+        if kind.len() == label.len() {
+            return label.to_string();
+        }
+        let n = counts.entry(kind.to_string()).or_default();
+        *n += 1;
+        let name = format!("{}_{}", kind, n);
+        names.insert(label.to_string(), name.clone());
+        name
+    };
+
+    lines
+        .into_iter()
+        .map(|line| {
+            let mut out = String::with_capacity(line.len());
+            let mut rest = line.as_str();
+            while let Some(start) = rest.find(".L") {
+                let preceded = rest[..start]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+                let len = rest[start..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                    .unwrap_or(rest.len() - start);
+                out.push_str(&rest[..start]);
+                let label = &rest[start..start + len];
+                match preceded {
+                    true => out.push_str(label),
+                    false => out.push_str(&rename(label)),
+                }
+                rest = &rest[start + len..];
+            }
+            out.push_str(rest);
+            out
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn labels_in_order_of_appearance() {
+        let asm = lines(&[
+            "    lea rsi, [rip + .Lanon.00d34e4c18d2944d3d12f792089a490b.3]",
+            "    je .LBB6_4",
+            "    lea rdi, [rip + .Lanon.00d34e4c18d2944d3d12f792089a490b.4]",
+            ".LBB6_4:",
+            "    je .LBB6_7",
+            "    lea rdi, [rip + .Lanon.00d34e4c18d2944d3d12f792089a490b.3]",
+        ]);
+        assert_eq!(
+            number_labels(asm),
+            lines(&[
+                "    lea rsi, [rip + .Lanon_1]",
+                "    je .LBB_1",
+                "    lea rdi, [rip + .Lanon_2]",
+                ".LBB_1:",
+                "    je .LBB_2",
+                "    lea rdi, [rip + .Lanon_1]",
+            ])
+        );
+    }
+
+    #[test]
+    fn other_names_untouched() {
+        let asm = lines(&[
+            "    call qword ptr [rip + std::io::stdio::_print@GOTPCREL]",
+            "    mov x.Ly, 1",
+            ".L:",
+        ]);
+        assert_eq!(number_labels(asm.clone()), asm);
+    }
+
+    #[test]
+    fn noise() {
+        assert!(is_noise("    .cfi_def_cfa_offset 16"));
+        assert!(is_noise(".Ltmp3:"));
+        assert!(is_noise("    # %bb.0:"));
+        assert!(!is_noise(".LBB6_4:"));
+        assert!(!is_noise("    ret"));
+    }
+
+    #[test]
+    fn symbols_referenced_by_the_wrapper() {
+        assert_eq!(
+            referenced_symbol("    lea rax, [rip + _ZN6traits12ex_use_notif17h0c54d1bfee1d702fE]"),
+            Some("_ZN6traits12ex_use_notif17h0c54d1bfee1d702fE")
+        );
+        assert_eq!(
+            referenced_symbol("    mov rax, qword ptr [rip + foo@GOTPCREL]"),
+            Some("foo")
+        );
+        assert_eq!(referenced_symbol("    ret"), None);
+    }
+}
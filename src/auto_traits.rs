@@ -0,0 +1,260 @@
+use crate::chapters::thread_safety::{
+    Bound, INTERIOR_MUTABILITY, PRELUDE, SHARED_OWNERSHIP, Wrapper,
+};
+use crate::{probe, region};
+
+const TRAITS: [&str; 2] = ["Send", "Sync"];
+
+// A wrapper must not implement the auto trait `t` under weaker bounds
+// than claimed: the probe of `assert` must fail to compile, with the
+// E0277 error naming `t`.
+struct Negative {
+    claim: String,
+    t: &'static str,
+    assert: String,
+}
+
+pub fn verify() -> Result<(), String> {
+    let wrappers: Vec<&Wrapper> = INTERIOR_MUTABILITY
+        .iter()
+        .chain(SHARED_OWNERSHIP.iter())
+        .collect();
+    let (asserts, negatives) = claims(&wrappers);
+
+    let src = probe_source(&asserts);
+    let (ok, stderr) = probe::check("auto_traits", &src)?;
+    if !ok {
+        return Err(format!("auto trait claims don't hold:\n{}", stderr));
+    }
+
+    for (i, n) in negatives.iter().enumerate() {
+        let src = probe_source(std::slice::from_ref(&n.assert));
+        let (ok, stderr) = probe::check(&format!("auto_traits_fail_{}", i), &src)?;
+        if ok {
+            return Err(format!("auto trait claim doesn't hold: {}", n.claim));
+        }
+        if !lacks(&stderr, n.t) {
+            return Err(format!(
+                "auto trait claim `{}` fails to compile for another reason:\n{}",
+                n.claim, stderr
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Assertions of the claimed bounds of the wrappers, and the claims that
+// each condition on T is necessary.
+fn claims(wrappers: &[&Wrapper]) -> (Vec<String>, Vec<Negative>) {
+    let mut asserts: Vec<String> = Vec::new();
+    let mut negatives: Vec<Negative> = Vec::new();
+    for w in wrappers {
+        for (t, bound) in TRAITS.iter().zip([&w.send, &w.sync]) {
+            match bound {
+                Bound::If(traits) => {
+                    asserts.push(assertion(w.ty, t, traits));
+                    // each condition on T must be necessary
+                    for i in 0..traits.len() {
+                        let mut weaker = traits.to_vec();
+                        weaker.remove(i);
+                        negatives.push(Negative {
+                            claim: format!("{} is {} without T: {}", w.ty, t, traits[i]),
+                            t,
+                            assert: assertion(w.ty, t, &weaker),
+                        });
+                    }
+                }
+                Bound::Never => negatives.push(Negative {
+                    claim: format!("{} is never {}", w.ty, t),
+                    t,
+                    assert: assertion(w.ty, t, &["Send", "Sync"]),
+                }),
+            }
+        }
+    }
+    (asserts, negatives)
+}
+
+// Whether the compiler rejected a probe because a type doesn't
+// implement the auto trait `t`.
+fn lacks(stderr: &str, t: &str) -> bool {
+    stderr.contains("error[E0277]") && stderr.contains(&format!("`{}`", t))
+}
+
+// Writes the summary tables, and the single-row table of each wrapper,
+// to the regions of the given markdown file.
+pub fn write_tables(fp: &str) -> Result<(), String> {
+    region::fill(fp, "interior-mutability", &table(INTERIOR_MUTABILITY))?;
+    region::fill(fp, "shared-ownership", &table(SHARED_OWNERSHIP))?;
+
+    for w in INTERIOR_MUTABILITY.iter().chain(SHARED_OWNERSHIP.iter()) {
+        region::fill(fp, w.ty, &table(std::slice::from_ref(w)))?;
+    }
+
+    Ok(())
+}
+
+fn table(wrappers: &[Wrapper]) -> Vec<String> {
+    let mut lines = vec![
+        "| Type | Provides | Accessors | Panics| Send | Sync |".to_string(),
+        "|:---:|:---:|:---:|:---:|:---:|:---:|".to_string(),
+    ];
+
+    for w in wrappers {
+        lines.push(format!(
+            "| `{}` | {} | {} | {} | {} | {} |",
+            w.ty,
+            w.provides,
+            w.accessors,
+            w.panics,
+            cell(&w.send),
+            cell(&w.sync)
+        ));
+    }
+
+    lines
+}
+
+fn cell(bound: &Bound) -> String {
+    match bound {
+        Bound::If(traits) => format!("✅<br><sub>(if T is {})</sub>", traits.join(" + ")),
+        Bound::Never => "🚫".to_string(),
+    }
+}
+
+fn assertion(ty: &str, t: &str, traits: &[&str]) -> String {
+    let bounds = if traits.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", traits.join(" + "))
+    };
+    format!(
+        "    fn _f<T{}>() {{ is_{}::<{}>(); }}",
+        bounds,
+        t.to_lowercase(),
+        ty
+    )
+}
+
+fn probe_source(asserts: &[String]) -> String {
+    let mut out = vec![
+        PRELUDE.to_string(),
+        String::new(),
+        "fn is_send<T: Send>() {}".to_string(),
+        "fn is_sync<T: Sync>() {}".to_string(),
+        String::new(),
+    ];
+
+    // every assertion in its own scope
+    for a in asserts {
+        out.push("const _: () = {".to_string());
+        out.push(a.clone());
+        out.push("};".to_string());
+    }
+
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MUTEX: Wrapper = Wrapper {
+        ty: "Mutex<T>",
+        provides: "",
+        accessors: "",
+        panics: "",
+        send: Bound::If(&["Send"]),
+        sync: Bound::If(&["Send"]),
+    };
+
+    const RC: Wrapper = Wrapper {
+        ty: "Rc<T>",
+        provides: "",
+        accessors: "",
+        panics: "",
+        send: Bound::Never,
+        sync: Bound::Never,
+    };
+
+    #[test]
+    fn claims_of_the_bounds() {
+        let arc = Wrapper {
+            ty: "Arc<T>",
+            sync: Bound::If(&["Send", "Sync"]),
+            ..MUTEX
+        };
+        let (asserts, negatives) = claims(&[&arc, &RC]);
+        assert_eq!(
+            asserts,
+            [
+                "    fn _f<T: Send>() { is_send::<Arc<T>>(); }",
+                "    fn _f<T: Send + Sync>() { is_sync::<Arc<T>>(); }",
+            ]
+        );
+
+        let negatives: Vec<(&str, &str, &str)> = negatives
+            .iter()
+            .map(|n| (n.claim.as_str(), n.t, n.assert.as_str()))
+            .collect();
+        assert_eq!(
+            negatives,
+            [
+                (
+                    "Arc<T> is Send without T: Send",
+                    "Send",
+                    "    fn _f<T>() { is_send::<Arc<T>>(); }"
+                ),
+                (
+                    "Arc<T> is Sync without T: Send",
+                    "Sync",
+                    "    fn _f<T: Sync>() { is_sync::<Arc<T>>(); }"
+                ),
+                (
+                    "Arc<T> is Sync without T: Sync",
+                    "Sync",
+                    "    fn _f<T: Send>() { is_sync::<Arc<T>>(); }"
+                ),
+                (
+                    "Rc<T> is never Send",
+                    "Send",
+                    "    fn _f<T: Send + Sync>() { is_send::<Rc<T>>(); }"
+                ),
+                (
+                    "Rc<T> is never Sync",
+                    "Sync",
+                    "    fn _f<T: Send + Sync>() { is_sync::<Rc<T>>(); }"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn probe_scopes() {
+        let src = probe_source(&["    fn _f() {}".to_string(), "    fn _f() {}".to_string()]);
+        assert!(src.starts_with(PRELUDE));
+        assert!(src.ends_with(
+            "fn is_send<T: Send>() {}\nfn is_sync<T: Sync>() {}\n\nconst _: () = {\n    fn _f() {}\n};\nconst _: () = {\n    fn _f() {}\n};"
+        ));
+    }
+
+    #[test]
+    fn probe_errors() {
+        let (ok, stderr) = probe::check(
+            "auto_traits_test_mutex",
+            &probe_source(&[assertion("Mutex<T>", "Sync", &[])]),
+        )
+        .unwrap();
+        assert!(!ok);
+        assert!(lacks(&stderr, "Sync"), "{}", stderr);
+
+        let (ok, stderr) = probe::check(
+            "auto_traits_test_typo",
+            &probe_source(&[assertion("Mutex<U>", "Sync", &["Send"])]),
+        )
+        .unwrap();
+        assert!(!ok);
+        assert!(!lacks(&stderr, "Sync"), "{}", stderr);
+    }
+}
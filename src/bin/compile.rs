@@ -1,3 +1,271 @@
+use rust_handbook::{Filter, Format};
+use std::io::{self, Read, Write};
+use std::process;
+use std::slice::Iter;
+
+const USAGE: &str = "usage:
+    compile [build [--format markdown|mdbook] [--out DIR] [--tag TAG]... [--status STATUS]...]
+    compile check-links [--format markdown|mdbook] [--out DIR]
+    compile lint [--format text|json] [FILE]...
+    compile spell [--fix] [FILE]...
+    compile words
+    compile fmt [--check] [FILE]...
+    compile new <chapter>
+    compile preprocess [supports <renderer>]";
+
 fn main() {
-    rust_handbook::compile_docs().unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    match args.first().map(|a| a.as_str()) {
+        None => rust_handbook::compile_docs(),
+        Some("build") => build(&args[1..]),
+        Some("check-links") => check_links(&args[1..]),
+        Some("lint") => lint(&args[1..]),
+        Some("spell") => spell(&args[1..]),
+        Some("words") => words(&args[1..]),
+        Some("fmt") => fmt(&args[1..]),
+        Some("new") => new(&args[1..]),
+        Some("preprocess") => preprocess(&args[1..]),
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(cmd) => Err(format!("unknown command `{}`\n{}", cmd, USAGE)),
+    }
+}
+
+fn build(args: &[String]) -> Result<(), String> {
+    let mut format = Format::Markdown;
+    let mut dir: Option<String> = None;
+    let mut filter = Filter::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = Format::parse(value(&mut args, arg)?)?,
+            "--out" => dir = Some(value(&mut args, arg)?.to_string()),
+            "--tag" => filter.tags.push(value(&mut args, arg)?.to_string()),
+            "--status" => filter.statuses.push(value(&mut args, arg)?.to_string()),
+            _ => return Err(format!("unknown option `{}`\n{}", arg, USAGE)),
+        }
+    }
+
+    let dir = dir.unwrap_or(format.default_dir().to_string());
+    rust_handbook::build_with(format, &dir, &filter, Vec::new())
+}
+
+// Checks the links of previously built docs, printing the broken ones
+// and the external ones, which are not fetched.
+fn check_links(args: &[String]) -> Result<(), String> {
+    let mut format = Format::Markdown;
+    let mut dir: Option<String> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = Format::parse(value(&mut args, arg)?)?,
+            "--out" => dir = Some(value(&mut args, arg)?.to_string()),
+            _ => return Err(format!("unknown option `{}`\n{}", arg, USAGE)),
+        }
+    }
+
+    let dir = dir.unwrap_or(format.default_dir().to_string());
+    let report = rust_handbook::check_links(format, &dir)?;
+
+    if !report.external.is_empty() {
+        println!("external links (not checked):");
+        for (file, line, url) in report.external.iter() {
+            println!("    {}:{}: {}", file, line, url);
+        }
+    }
+    for b in report.broken.iter() {
+        let source = match &b.source {
+            Some((src, line)) => format!(" (from {}:{})", src, line),
+            None => String::new(),
+        };
+        eprintln!(
+            "{}:{}: broken link `{}`: {}{}",
+            b.file, b.line, b.target, b.reason, source
+        );
+    }
+
+    match report.broken.len() {
+        0 => Ok(()),
+        n => Err(format!("{} broken link(s)", n)),
+    }
+}
+
+fn lint(args: &[String]) -> Result<(), String> {
+    let mut json = false;
+    let mut files = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match value(&mut args, arg)? {
+                "text" => json = false,
+                "json" => json = true,
+                f => return Err(format!("unknown lint format `{}`", f)),
+            },
+            _ if arg.starts_with("--") => {
+                return Err(format!("unknown option `{}`\n{}", arg, USAGE));
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    let diagnostics = rust_handbook::lint(&files)?;
+    if json {
+        println!("{}", rust_handbook::lint::to_json(&diagnostics));
+    } else {
+        for d in diagnostics.iter() {
+            println!("{}", rust_handbook::lint::to_text(d));
+        }
+    }
+
+    match diagnostics.len() {
+        0 => Ok(()),
+        n => Err(format!("{} lint problem(s)", n)),
+    }
+}
+
+fn spell(args: &[String]) -> Result<(), String> {
+    let mut fix = false;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--fix" => fix = true,
+            _ if arg.starts_with("--") => {
+                return Err(format!("unknown option `{}`\n{}", arg, USAGE));
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    // every fix is confirmed, answers are read from stdin
+    let stdin = io::stdin();
+    let mut confirm = |m: &rust_handbook::spell::Misspelling| -> bool {
+        print!(
+            "{}:{}:{}: replace `{}` with `{}`? [y/N] ",
+            m.file,
+            m.line,
+            m.column,
+            m.word,
+            m.fix.as_deref().unwrap_or_default()
+        );
+        let _ = io::stdout().flush();
+        let mut answer = String::new();
+        stdin.read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+    };
+    let confirm: Option<&mut dyn FnMut(&rust_handbook::spell::Misspelling) -> bool> = match fix {
+        true => Some(&mut confirm),
+        false => None,
+    };
+    let misspellings = rust_handbook::spell(&files, confirm)?;
+    for m in misspellings.iter() {
+        let suggestions = match m.suggestions.as_slice() {
+            [] => String::new(),
+            s => format!(" (did you mean {}?)", s.join(", ")),
+        };
+        println!(
+            "{}:{}:{}: unknown word `{}`{}",
+            m.file, m.line, m.column, m.word, suggestions
+        );
+    }
+
+    match misspellings.len() {
+        0 => Ok(()),
+        n => Err(format!("{} unknown word(s)", n)),
+    }
+}
+
+// Regenerates the word list bundled with `spell` from the local Rust
+// documentation.
+fn words(args: &[String]) -> Result<(), String> {
+    if let Some(arg) = args.first() {
+        return Err(format!("unexpected argument `{}`\n{}", arg, USAGE));
+    }
+    let n = rust_handbook::wordlist::write()?;
+    println!(
+        "wrote {} words to {}",
+        n,
+        rust_handbook::wordlist::WORDS_FILE
+    );
+    Ok(())
+}
+
+fn fmt(args: &[String]) -> Result<(), String> {
+    let mut check = false;
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with("--") => {
+                return Err(format!("unknown option `{}`\n{}", arg, USAGE));
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+
+    let changed = rust_handbook::fmt(&files, check)?;
+    for fp in changed.iter() {
+        match check {
+            true => println!("{}: not formatted", fp),
+            false => println!("formatted {}", fp),
+        }
+    }
+
+    match changed.len() {
+        n if check && n > 0 => Err(format!("{} file(s) not formatted", n)),
+        _ => Ok(()),
+    }
+}
+
+fn new(args: &[String]) -> Result<(), String> {
+    let name = match args {
+        [name] => name,
+        _ => return Err(format!("expected a chapter name\n{}", USAGE)),
+    };
+
+    let fp = rust_handbook::scaffold::new_chapter(name)?;
+    println!("created {}", fp);
+    Ok(())
+}
+
+// mdBook preprocessor: answers the renderer handshake, or reads the book
+// from stdin and writes it back expanded.
+fn preprocess(args: &[String]) -> Result<(), String> {
+    if let Some(arg) = args.first() {
+        return match (arg.as_str(), args.get(1)) {
+            ("supports", Some(renderer)) => {
+                if !rust_handbook::mdbook::supports(renderer) {
+                    process::exit(1);
+                }
+                Ok(())
+            }
+            _ => Err(format!("unknown arguments `{}`\n{}", args.join(" "), USAGE)),
+        };
+    }
+
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| format!("reading stdin: {}", e))?;
+
+    let out = rust_handbook::mdbook::preprocess(&input)?;
+    io::stdout()
+        .write_all(out.as_bytes())
+        .map_err(|e| format!("writing stdout: {}", e))
+}
+
+fn value<'a>(args: &mut Iter<'a, String>, opt: &str) -> Result<&'a str, String> {
+    args.next()
+        .map(|v| v.as_str())
+        .ok_or(format!("missing value for `{}`", opt))
 }
@@ -0,0 +1,565 @@
+use crate::Format;
+use crate::compile::{Doc, Section};
+use crate::probe;
+
+// Callouts are marked with an emoji, at the start of a paragraph of prose
+// or of a comment leading some code:
+//
+//     //t ⚠️ Trait objects can't be used with every trait.
+//
+//     // ✅ Cleaner signature.
+//     pub fn ex_use_notif_5<T, U>(disclaimer: U, item: T)
+//
+// Top-level comments of code sections and the first comment of fenced
+// code in prose are lifted out of the code. Comments inside functions
+// are left as they are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Error,
+    Ok,
+    Warning,
+}
+
+const MARKERS: &[(&str, Kind)] = &[
+    ("❌", Kind::Error),
+    ("✅", Kind::Ok),
+    ("⚠️", Kind::Warning),
+    ("⚠", Kind::Warning),
+];
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Error => "error",
+            Kind::Ok => "ok",
+            Kind::Warning => "warning",
+        }
+    }
+
+    // GitHub alert type.
+    fn alert(&self) -> &'static str {
+        match self {
+            Kind::Error => "CAUTION",
+            Kind::Ok => "TIP",
+            Kind::Warning => "WARNING",
+        }
+    }
+}
+
+// Kind of the marker at the start of the text, and the text after it.
+pub fn marker(text: &str) -> Option<(Kind, &str)> {
+    let text = text.trim_start();
+    MARKERS
+        .iter()
+        .find_map(|(m, kind)| text.strip_prefix(m).map(|rest| (*kind, rest.trim_start())))
+}
+
+// Code marked ❌ must not compile, failing with the error code the
+// callout starts with:
+//
+//     //t ```rust
+//     //t // ❌ E0119: Conflicting implementation.
+//     //t impl ToString for WhatsappMessage { ... }
+//     //t ```
+//
+//     // ❌ E0382: the closure was moved by the first call.
+//     //
+//     // my_fn_once();
+//
+// Fenced code in prose is type-checked after the code of the chapter.
+// Inside functions, the code is commented out after the callout and an
+// empty `//` line, and type-checked uncommented in place. The code of the
+// chapter itself always compiles, so it can't be marked at the top level.
+pub fn verify(fp: &str, src: &str) -> Result<(), String> {
+    let lines: Vec<&str> = src.lines().collect();
+
+    let mut fenced = false;
+    let mut rust: Option<(usize, Vec<&str>)> = None;
+    for (i, line) in lines.iter().enumerate() {
+        if let Some((Kind::Error, _)) = top_level_comment(line).and_then(marker) {
+            return Err(format!(
+                "{}:{}: code marked ❌ is part of the chapter and compiles, \
+                 move it to fenced code in the prose",
+                fp,
+                i + 1
+            ));
+        }
+
+        if let Some((Kind::Error, text)) = inner_comment(line).and_then(marker) {
+            let code = error_code(text).ok_or(missing_code(fp, i + 1))?;
+            let probe_src = uncomment(&lines, i).ok_or(format!(
+                "{}:{}: code marked ❌ must follow the callout, commented out \
+                 after an empty `//` line",
+                fp,
+                i + 1
+            ))?;
+            fails_with(fp, i + 1, code, &probe_src)?;
+            continue;
+        }
+
+        let text = match line.strip_prefix("//t") {
+            Some(text) if !text.starts_with('!') => text.strip_prefix(' ').unwrap_or(text),
+            _ => continue,
+        };
+        if !text.trim_start().starts_with("```") {
+            if let Some((_, code)) = rust.as_mut() {
+                code.push(text);
+            }
+            continue;
+        }
+
+        fenced = !fenced;
+        if fenced {
+            let lang = text.trim_start().trim_start_matches('`');
+            if lang.is_empty() || lang.starts_with("rust") {
+                rust = Some((i + 1, Vec::new()));
+            }
+            continue;
+        }
+        let (start, code) = match rust.take() {
+            Some(fence) => fence,
+            None => continue,
+        };
+
+        let leading = code.iter().find(|l| !l.trim().is_empty());
+        let text = match leading.and_then(|l| comment(l)).and_then(marker) {
+            Some((Kind::Error, text)) => text,
+            _ => continue,
+        };
+        let error = error_code(text).ok_or(missing_code(fp, start))?;
+        let probe_src = format!("{}\n{}\n", src, code.join("\n"));
+        fails_with(fp, start, error, &probe_src)?;
+    }
+
+    Ok(())
+}
+
+// Type-checks the probe of the code marked ❌ at `line`, which must fail
+// with the error `code`.
+fn fails_with(fp: &str, line: usize, code: &str, probe_src: &str) -> Result<(), String> {
+    let probe_name = probe::name(fp, &format!("callout_{}", line));
+    let (ok, stderr) = probe::check(&probe_name, probe_src)?;
+    if ok {
+        return Err(format!("{}:{}: code marked ❌ compiles", fp, line));
+    }
+    if !stderr.contains(&format!("error[{}]", code)) {
+        return Err(format!(
+            "{}:{}: code marked ❌ doesn't fail with {}:\n{}",
+            fp, line, code, stderr
+        ));
+    }
+    Ok(())
+}
+
+fn missing_code(fp: &str, line: usize) -> String {
+    format!(
+        "{}:{}: code marked ❌ must start with the expected error code, e.g. `❌ E0277`",
+        fp, line
+    )
+}
+
+// Error code at the start of the text of a ❌ callout, e.g. `E0277` in
+// `E0277: the trait bound is not satisfied`.
+fn error_code(text: &str) -> Option<&str> {
+    let code = text.split_whitespace().next()?.trim_end_matches(':');
+    let digits = code.strip_prefix('E')?;
+    match digits.len() == 4 && digits.chars().all(|c| c.is_ascii_digit()) {
+        true => Some(code),
+        false => None,
+    }
+}
+
+// Source with the code commented out after the ❌ callout at `i`
+// uncommented in place, if there is any.
+fn uncomment(lines: &[&str], i: usize) -> Option<String> {
+    let end = lines[i..]
+        .iter()
+        .position(|l| inner_comment(l).is_none())
+        .map_or(lines.len(), |n| i + n);
+    let sep = i + lines[i..end].iter().position(|l| l.trim() == "//")?;
+    if sep + 1 == end {
+        return None;
+    }
+
+    let mut out: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    for l in out[sep + 1..end].iter_mut() {
+        let indent = l.len() - l.trim_start().len();
+        *l = format!("{}{}", &l[..indent], strip_space(&l[indent + 2..]));
+    }
+    Some(out.join("\n"))
+}
+
+// Splits the prose and code sections around their callouts.
+pub fn extract(sections: Vec<Section>) -> Vec<Section> {
+    let mut out = Vec::new();
+    for s in sections {
+        match s {
+            Section::Comment { start, lines } => {
+                for piece in split_prose(lines) {
+                    out.push(match piece {
+                        Piece::Lines(i, lines) => Section::Comment {
+                            start: start + i,
+                            lines,
+                        },
+                        Piece::Callout(i, kind, lines) => Section::Callout {
+                            start: start + i,
+                            kind,
+                            lines,
+                        },
+                    });
+                }
+            }
+            Section::Code {
+                start,
+                lines,
+                attrs,
+            } => {
+                let mut attrs = Some(attrs);
+                for piece in split_code(lines) {
+                    out.push(match piece {
+                        Piece::Lines(i, lines) => Section::Code {
+                            start: start + i,
+                            lines,
+                            attrs: attrs.take().unwrap_or_default(),
+                        },
+                        Piece::Callout(i, kind, lines) => Section::Callout {
+                            start: start + i,
+                            kind,
+                            lines,
+                        },
+                    });
+                }
+            }
+            s => out.push(s),
+        }
+    }
+    out
+}
+
+// Renders the callouts for the output format: GitHub alerts in markdown,
+// `admonition` blocks in HTML.
+pub fn render(doc: &mut Doc, format: Format) {
+    for s in doc.sections_mut() {
+        let (kind, lines) = match s {
+            Section::Callout { kind, lines, .. } => (*kind, lines),
+            _ => continue,
+        };
+
+        let mut out = Vec::with_capacity(lines.len() + 4);
+        match format {
+            Format::Markdown => {
+                out.push(format!("> [!{}]", kind.alert()));
+                out.extend(lines.iter().map(|l| format!("> {}", l)));
+            }
+            // blank lines around the markdown, for it to be rendered
+            Format::Mdbook => {
+                out.push(format!("<div class=\"admonition {}\">", kind.name()));
+                out.push(String::new());
+                out.extend(lines.iter().cloned());
+                out.push(String::new());
+                out.push("</div>".to_string());
+            }
+        }
+        *lines = out;
+    }
+}
+
+// Runs of lines of a section (with their offset in it) and the callouts
+// between them.
+enum Piece {
+    Lines(usize, Vec<String>),
+    Callout(usize, Kind, Vec<String>),
+}
+
+fn split_prose(lines: Vec<String>) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut current: (usize, Vec<String>) = (0, Vec::new());
+    let mut fenced = false;
+
+    let mut i = 0;
+    while i < lines.len() {
+        let line = &lines[i];
+        if line.trim_start().starts_with("```") {
+            fenced = !fenced;
+            let comment = match fenced {
+                true => leading_comment(&lines[i + 1..]),
+                false => None,
+            };
+            match comment {
+                Some((kind, text, n)) => {
+                    flush(&mut pieces, &mut current, i);
+                    pieces.push(Piece::Callout(i + 1, kind, text));
+                    // the code maps to its lines, only the fence is off
+                    current.0 = i + n;
+                    current.1.push(line.clone());
+                    i += 1 + n;
+                }
+                None => {
+                    current.1.push(line.clone());
+                    i += 1;
+                }
+            }
+            continue;
+        }
+
+        let starts_paragraph = i == 0 || lines[i - 1].trim().is_empty();
+        let callout = marker(line).filter(|_| !fenced && starts_paragraph);
+        let (kind, first) = match callout {
+            Some(callout) => callout,
+            None => {
+                current.1.push(line.clone());
+                i += 1;
+                continue;
+            }
+        };
+
+        let end = lines[i..]
+            .iter()
+            .position(|l| l.trim().is_empty())
+            .map_or(lines.len(), |n| i + n);
+        let mut text = vec![first.to_string()];
+        text.extend(lines[i + 1..end].iter().cloned());
+
+        flush(&mut pieces, &mut current, i);
+        pieces.push(Piece::Callout(i, kind, text));
+        current.0 = end;
+        i = end;
+    }
+
+    flush(&mut pieces, &mut current, lines.len());
+    pieces
+}
+
+fn split_code(lines: Vec<String>) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut current: (usize, Vec<String>) = (0, Vec::new());
+
+    let mut i = 0;
+    while i < lines.len() {
+        let callout = top_level_comment(&lines[i]).and_then(marker);
+        let (kind, first) = match callout {
+            Some(callout) => callout,
+            None => {
+                current.1.push(lines[i].clone());
+                i += 1;
+                continue;
+            }
+        };
+
+        let mut text = vec![first.to_string()];
+        let mut end = i + 1;
+        while let Some(comment) = lines.get(end).and_then(|l| top_level_comment(l)) {
+            if marker(comment).is_some() {
+                break;
+            }
+            text.push(comment.to_string());
+            end += 1;
+        }
+
+        flush(&mut pieces, &mut current, i);
+        pieces.push(Piece::Callout(i, kind, text));
+        current.0 = end;
+        i = end;
+    }
+
+    flush(&mut pieces, &mut current, lines.len());
+    pieces
+}
+
+// Pushes the lines collected so far, unless blank, and starts a new run.
+fn flush(pieces: &mut Vec<Piece>, current: &mut (usize, Vec<String>), next: usize) {
+    let (start, mut lines) = std::mem::replace(current, (next, Vec::new()));
+    let leading = lines.iter().take_while(|l| l.trim().is_empty()).count();
+    lines.drain(..leading);
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    if !lines.is_empty() {
+        pieces.push(Piece::Lines(start + leading, lines));
+    }
+}
+
+// Marked comment at the start of some code, with its text and the number
+// of lines it spans.
+fn leading_comment(code: &[String]) -> Option<(Kind, Vec<String>, usize)> {
+    let (kind, first) = code.first().and_then(|l| comment(l)).and_then(marker)?;
+
+    let mut text = vec![first.to_string()];
+    for l in code[1..].iter() {
+        match comment(l) {
+            Some(c) if marker(c).is_none() => text.push(c.to_string()),
+            _ => break,
+        }
+    }
+    let n = text.len();
+    Some((kind, text, n))
+}
+
+// Text of a `//` comment line.
+fn comment(line: &str) -> Option<&str> {
+    line.trim_start().strip_prefix("//").map(strip_space)
+}
+
+// Text of an indented `//` comment, directives and doc comments excluded.
+fn inner_comment(line: &str) -> Option<&str> {
+    if !line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    match line.trim_start().strip_prefix("//") {
+        Some(rest) if !rest.starts_with(['/', '!']) && !rest.starts_with("t!") => {
+            Some(strip_space(rest))
+        }
+        _ => None,
+    }
+}
+
+// Text of a `//` comment at column 0, directives and doc comments excluded.
+fn top_level_comment(line: &str) -> Option<&str> {
+    match line.strip_prefix("//") {
+        Some(rest) if !rest.starts_with(['/', 't', '!']) => Some(strip_space(rest)),
+        _ => None,
+    }
+}
+
+fn strip_space(s: &str) -> &str {
+    s.strip_prefix(' ').unwrap_or(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(src: &str) -> Vec<String> {
+        src.lines().map(String::from).collect()
+    }
+
+    // (kind of section, first line, lines) of every section.
+    fn summary(sections: &[Section]) -> Vec<(&'static str, usize, Vec<String>)> {
+        sections
+            .iter()
+            .map(|s| match s {
+                Section::Comment { start, lines } => ("comment", *start, lines.clone()),
+                Section::Code { start, lines, .. } => ("code", *start, lines.clone()),
+                Section::Callout { start, kind, lines } => (kind.name(), *start, lines.clone()),
+                _ => panic!("unexpected section {:?}", s),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn markers() {
+        assert_eq!(marker("  ❌ Nope"), Some((Kind::Error, "Nope")));
+        assert_eq!(marker("✅ok"), Some((Kind::Ok, "ok")));
+        assert_eq!(marker("⚠️ Careful"), Some((Kind::Warning, "Careful")));
+        assert_eq!(marker("⚠ Careful"), Some((Kind::Warning, "Careful")));
+        assert_eq!(marker("Not ❌ here"), None);
+    }
+
+    #[test]
+    fn prose_callouts() {
+        let prose = Section::Comment {
+            start: 10,
+            lines: lines(
+                "Intro.\n\n⚠️ First line\nsecond line.\n\nOutro ✅ unmarked.\n```rust\n// ❌ Doesn't compile\n// at all.\nlet x: u8 = \"\";\n```",
+            ),
+        };
+        assert_eq!(
+            summary(&extract(vec![prose])),
+            [
+                ("comment", 10, lines("Intro.")),
+                ("warning", 12, lines("First line\nsecond line.")),
+                ("comment", 15, lines("Outro ✅ unmarked.")),
+                ("error", 17, lines("Doesn't compile\nat all.")),
+                ("comment", 18, lines("```rust\nlet x: u8 = \"\";\n```")),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_callouts() {
+        let code = Section::Code {
+            start: 20,
+            lines: lines(
+                "// ✅ Cleaner signature,\n// with a bound.\nfn a() {\n    // ❌ inside, kept\n}\n//t!code\n/// ⚠️ doc, kept\nfn b() {}",
+            ),
+            attrs: Default::default(),
+        };
+        assert_eq!(
+            summary(&extract(vec![code])),
+            [
+                ("ok", 20, lines("Cleaner signature,\nwith a bound.")),
+                (
+                    "code",
+                    22,
+                    lines(
+                        "fn a() {\n    // ❌ inside, kept\n}\n//t!code\n/// ⚠️ doc, kept\nfn b() {}"
+                    )
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn marked_chapter_code() {
+        let src = "//t Prose.\n\n// ❌ Compiles anyway.\nfn a() {}\n";
+        assert_eq!(
+            verify("x.rs", src).unwrap_err(),
+            "x.rs:3: code marked ❌ is part of the chapter and compiles, move it to fenced code in the prose"
+        );
+    }
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(error_code("E0277: not Send."), Some("E0277"));
+        assert_eq!(error_code("E0382 moved"), Some("E0382"));
+        assert_eq!(error_code("Doesn't compile."), None);
+        assert_eq!(error_code("E277: short"), None);
+    }
+
+    #[test]
+    fn commented_out_code() {
+        let lines = [
+            "fn a(v: Vec<u8>) {",
+            "    drop(v);",
+            "    // ❌ E0382: moved,",
+            "    // by drop.",
+            "    //",
+            "    // if true {",
+            "    //     drop(v);",
+            "    // }",
+            "}",
+        ];
+        assert_eq!(
+            uncomment(&lines, 2).unwrap(),
+            "fn a(v: Vec<u8>) {\n    drop(v);\n    // ❌ E0382: moved,\n    // by drop.\n    //\n    if true {\n        drop(v);\n    }\n}"
+        );
+        assert_eq!(uncomment(&lines[..5], 2), None);
+        assert_eq!(uncomment(&lines[..4], 2), None);
+    }
+
+    #[test]
+    fn marked_code_fails_with_its_error() {
+        let chapter = |callout: &str| {
+            format!(
+                "fn a(v: Vec<u8>) {{\n    drop(v);\n    // ❌ {}\n    //\n    // drop(v);\n}}\n",
+                callout
+            )
+        };
+        assert!(verify("x.rs", &chapter("E0382: moved.")).is_ok());
+        assert!(
+            verify("x.rs", &chapter("E0308: moved."))
+                .unwrap_err()
+                .starts_with("x.rs:3: code marked ❌ doesn't fail with E0308:")
+        );
+        assert_eq!(
+            verify("x.rs", &chapter("Moved.")).unwrap_err(),
+            "x.rs:3: code marked ❌ must start with the expected error code, e.g. `❌ E0277`"
+        );
+
+        let fenced = "//t ```rust\n//t // ❌ E0308: not a u8.\n//t fn b() -> u8 { 1 }\n//t ```\n";
+        assert_eq!(
+            verify("x.rs", fenced).unwrap_err(),
+            "x.rs:1: code marked ❌ compiles"
+        );
+    }
+}
@@ -1,2 +1,3 @@
 pub mod closures;
+pub mod thread_safety;
 pub mod traits;
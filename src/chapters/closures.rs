@@ -1,15 +1,25 @@
+//t!meta title: Closures
+//t!meta summary: Anonymous functions capturing their environment, the `Fn`, `FnMut` and `FnOnce` traits and `move` closures.
+//t!meta authors: anBertoli
+//t!meta tags: closures, traits, ownership
+//t!meta prerequisites: traits
+//t!meta difficulty: intermediate
+//t!meta status: complete
+
 //t # Closures
 //t
-//t Rust’s closures are anonymous functions you can save in a variable
-//t or pass as arguments to other functions. You can create the closure
-//t in one place and then call the closure elsewhere to evaluate it in a
-//t different context. Unlike functions, closures can capture values
-//t from the scope in which they’re defined.
+//t Rust’s closures are anonymous functions you can save in a variable or pass
+//t as arguments to other functions. You can create the closure in one place and
+//t then call the closure elsewhere to evaluate it in a different context.
+//t Unlike functions, closures can capture values from the scope in which
+//t they’re defined.
 //t
-//t There are more differences between functions and closures. Closures
-//t don’t usually require you to annotate the types of the parameters or
-//t the return value like fn functions do. In addition, closures can capture
-//t values and references from the environment (scope).
+//t There are more differences between functions and closures. Closures don’t
+//t usually require you to annotate the types of the parameters or the return
+//t value like fn functions do. In addition, closures can capture values and
+//t references from the environment (scope).
+//t!nofmt
+//t!code title="Closure syntax" hl=4-7
 
 fn ex_closures() {
     let n = 3;
@@ -24,19 +34,19 @@ fn ex_closures() {
 
     let val = 3;
     let add_from_env = |x: i32| x + val;
-    println!("{}", add_from_env(2));
+    println!("{}", add_from_env(2)); // -> 5
 }
 
 //t ## Closure Traits
 //t
 //t Each closure is of an anonymous type the compiler creates. You can't refer
-//t to the closure concrete type explicitly. The way a closure captures
-//t and handles values from the environment affects which traits the closure
+//t to the closure concrete type explicitly. The way a closure captures and
+//t handles values from the environment affects which traits the closure
 //t automatically implements. The compiler will decide which of these to use
 //t based on what the body of the function does with the captured values.
-//t Closures will automatically implement one, two, or all three of the following
-//t traits, in an incremental fashion, depending on how the closure’s body
-//t handles the env values.
+//t Closures will automatically implement one, two, or all three of the
+//t following traits, in an incremental fashion, depending on how the closure’s
+//t body handles the env values.
 //t
 //t - `FnOnce`: consumes env values so can be called only once. A closure that
 //t consumes captured values (or drops them) will only implement FnOnce and none
@@ -48,41 +58,34 @@ fn ex_closures() {
 //t of FnOnce, so it is accepted when a FnOnce is present in a trait bound.
 //t
 //t - `Fn`: borrow immutably env values, applies to closures that don’t consume
-//t captured values and that don’t mutate captured values. These closures can
-//t be called more than once without mutating their environment, which is
-//t important in cases such as calling a closure multiple times concurrently.
-//t It's a subtype of FnMut, so it is accepted when a FnOnce or a FnMut is
-//t present in a trait bound.
+//t captured values and that don’t mutate captured values. These closures can be
+//t called more than once without mutating their environment, which is important
+//t in cases such as calling a closure multiple times concurrently. It's a
+//t subtype of FnMut, so it is accepted when a FnOnce or a FnMut is present in a
+//t trait bound.
 //t
-//t Every `Fn` meets the requirements for `FnMut`, and every `FnMut` meets
-//t the requirements for `FnOnce`. They’re not three separate categories.
-//t Instead, `Fn` is a subtype of `FnMut`, which is a subtype of `FnOnce`;
-//t this makes `Fn` the most specific type of closure.
+//t Every `Fn` meets the requirements for `FnMut`, and every `FnMut` meets the
+//t requirements for `FnOnce`. They’re not three separate categories. Instead,
+//t `Fn` is a subtype of `FnMut`, which is a subtype of `FnOnce`; this makes
+//t `Fn` the most specific type of closure.
 
 fn ex_multiple_calls() {
-
-    // ❌ The closure is a FnOnce because it consumes
-    // the string, so it can be called only one time.
     let non_copy_val = String::from("ehy");
+    //t!classify FnOnce
     let my_fn_once = || {
-        let str_bytes = non_copy_val.into_bytes();
+        let str_bytes = non_copy_val.into_bytes(); // (1)
         println!("str bytes: {:?}", str_bytes);
     };
 
     my_fn_once();
-    // my_fn_once(); // doesn't compile
-
-    // ✅ The closure is a FnMut because it modifies the string.
-    // It can be called multiple times because it doesn't consume
-    // any value. It is a subtype of FnOnce, so it is accepted
-    // by FnOnce trait bound.
+    // ❌ E0382: the closure was moved by the first call.
     //
-    // Note that the closure must be declared as mutable to be
-    // to mutate the captured values (you can think of it like
-    // a struct capturing vals/references).
+    // my_fn_once();
+
     let mut non_copy_val = String::from("ehy");
+    //t!classify FnMut
     let mut my_fn_mut = || {
-        non_copy_val.push_str(" guys");
+        non_copy_val.push_str(" guys"); // (2)
         String::new()
     };
 
@@ -90,11 +93,9 @@ fn ex_multiple_calls() {
     my_fn_mut();
     my_fn_mut();
 
-    // ✅ The closure is a Fn because it doesn't modify the
-    // string, so it can be called multiple times. It is a
-    // subtype of FnOnce so it is accepted by unwrap_or_else.
+    //t!classify Fn
     let my_fn = || {
-        println!("{:?}", non_copy_val);
+        println!("{:?}", non_copy_val); // (3)
         String::new()
     };
 
@@ -103,18 +104,37 @@ fn ex_multiple_calls() {
     my_fn();
 }
 
+//t (1) ❌ The closure is a FnOnce because it consumes the string, so it can be
+//t called only one time.
+//t (2) ✅ The closure is a FnMut because it modifies the string. It can be
+//t called multiple times because it doesn't consume any value. It is a subtype
+//t of FnOnce, so it is accepted by FnOnce trait bound. Note that the closure
+//t must be declared as mutable to be able to mutate the captured values (you
+//t can think of it like a struct capturing vals/references).
+//t (3) ✅ The closure is a Fn because it doesn't modify the string, so it can be
+//t called multiple times. It is a subtype of FnOnce so it is accepted by
+//t `unwrap_or_else`.
+
 //t ### `FnOnce`
 //t
-//t Using `FnOnce` in a trait bound expresses the constraint that the
-//t generic function/struct/item is only going to call the closure at
-//t most one time. Every closure trait is an `FnOnce` so all can be used
-//t in place of a `FnOnce`. A `FnOnce` closure is consumed after it is called.
+//t Using `FnOnce` in a trait bound (see [[traits#traits-as-bounds]]) expresses
+//t the constraint that the generic function/struct/item is only going to call
+//t the closure at most one time. Every closure trait is an `FnOnce` so all can
+//t be used in place of a `FnOnce`. A `FnOnce` closure is consumed after it is
+//t called.
+
+//t!step
+//t Take an `Option` of our own:
 
 enum MyOption<T> {
     Some(T),
     None,
 }
 
+//t!step
+//t Its `unwrap_or_else` method calls the closure at most once, when there is no
+//t value, so it bounds the closure with `FnOnce`:
+
 impl<T> MyOption<T> {
     pub fn unwrap_or_else<F>(self, f: F) -> T
     where
@@ -127,6 +147,9 @@ impl<T> MyOption<T> {
     }
 }
 
+//t!step
+//t Any closure can be passed to it:
+
 fn ex_trait_bounds_fn_once() {
 
     // ✅ Trivially, FnOnce is accepted by unwrap_or_else
@@ -161,11 +184,11 @@ fn ex_trait_bounds_fn_once() {
 
 //t ### `FnMut`
 //t
-//t FnMut is a subtype of FnOnce, so FnOnce closures don't satisfy
-//t FnMut trait bounds, while Fn closures do. A FnMut must be mut
-//t to be called. In the example the `map` function requires a
-//t closure that can be called multiple times (once for every item
-//t of the vector), and it's ok even if it mutates the captured env.
+//t FnMut is a subtype of FnOnce, so FnOnce closures don't satisfy FnMut trait
+//t bounds, while Fn closures do. A FnMut must be mut to be called. In the
+//t example the `map` function requires a closure that can be called multiple
+//t times (once for every item of the vector), and it's ok even if it mutates
+//t the captured env.
 
 fn map<V, U, F>(list: Vec<V>, mut map_fn: F) -> Vec<U>
 where
@@ -180,20 +203,25 @@ where
 
 fn ex_trait_bounds_fn_mut() {
 
-    // ❌ The closure is FnOnce because it consumes a variable from
-    // its environment, so it doesn't meet the `map` requirements.
+    // The closure is FnOnce because it consumes a variable from
+    // its environment.
     let non_copy_val = String::from("ehy");
-    let my_fn_once = || {
+    //t!classify FnOnce
+    let my_fn_once = |n: i32| {
         let str_bytes = non_copy_val.into_bytes();
         println!("str bytes: {:?}", str_bytes);
+        n + 1
     };
 
-    // map(vec![1, 2, 3, 4], my_fn_once); doesn't compile
+    // ❌ E0525: a FnOnce closure doesn't meet the `map` requirements.
+    //
+    // map(vec![1, 2, 3, 4], my_fn_once);
 
     // ✅ The closure is FnMut because it mutates the environment
     // but doesn’t consume anything, so it meets the `map` bound
     // requirements.
     let mut sum = 0;
+    //t!classify FnMut
     let my_fn_mut = |n| {
         sum += n;
         n + 1
@@ -204,6 +232,7 @@ fn ex_trait_bounds_fn_mut() {
     // ✅The closure is a Fn because it doesn't modify the environment.
     // It is a subtype of the required FnMut trait, so it is accepted
     // by the `map` function.
+    //t!classify Fn
     let my_fn = |n| format!("num: {:?}", n);
 
     map(vec![1, 2, 3, 4], my_fn);
@@ -211,9 +240,9 @@ fn ex_trait_bounds_fn_mut() {
 
 //t ### `Fn`
 //t
-//t `Fn` is a subtype of FnOnce and FnMut so FnOnce and FnMut closures
-//t doesn't satisfy Fn. It borrows env values immutably and can be
-//t called multiple times.
+//t `Fn` is a subtype of FnOnce and FnMut so FnOnce and FnMut closures doesn't
+//t satisfy Fn. It borrows env values immutably and can be called multiple
+//t times.
 
 fn requires_fn<F: Fn(i32)>(my_fn: F) {
     my_fn(1);
@@ -223,30 +252,37 @@ fn requires_fn<F: Fn(i32)>(my_fn: F) {
 
 fn example() {
 
-    // ❌ The closure is FnOnce because it moves out a variable from
-    // its environment, so it doesn't meet the `requires_fn` requirements.
+    // The closure is FnOnce because it moves out a variable from
+    // its environment.
     let mut strings: Vec<String> = vec![];
     let s = "str".to_string();
+    //t!classify FnOnce
     let my_fn_once = |n: i32| {
         strings.push(s);
         n + 1;
     };
 
-    // let res = requires_fn(my_fn_once); doesn't compile
+    // ❌ E0525: a FnOnce closure doesn't meet the `requires_fn`
+    // requirements.
+    //
+    // let res = requires_fn(my_fn_once);
 
-    // ❌ The closure is FnMut because it mutates the environment but
-    // doesn’t mutate or consumes anything from its environment. It
-    // doesn't meet the stricter Fn requirements.
+    // The closure is FnMut because it mutates the environment but
+    // doesn’t consume anything from its environment.
     let mut sum = 0;
+    //t!classify FnMut
     let my_fn_mut = |n: i32| {
         sum += n;
-        n + 1
     };
 
-    // let res = requires_fn(my_fn_mut); doesn't compile
+    // ❌ E0525: a FnMut closure doesn't meet the stricter Fn
+    // requirements.
+    //
+    // let res = requires_fn(my_fn_mut);
 
     // ✅ The closure is a Fn because it doesn't modify or consumes
     // the environment. It meets the Fn function requirements.
+    //t!classify Fn
     let my_fn = |n| {
         println!("num: {:?}", n);
     };
@@ -257,13 +293,13 @@ fn example() {
 
 //t ## Moving Values
 //t
-//t If you want to force the closure to take ownership of the values it
-//t uses in the environment, even though the body of the closure doesn’t
-//t strictly need ownership, you can use the `move` keyword before the
-//t parameters. Note that `move` and the trait of the closure are orthogonal
-//t features: whatever the trait of the closure is, `move` is an option.
-//t In other words: `move' determines how values are captured, the closure
-//t trait determines how values are used.
+//t If you want to force the closure to take ownership of the values it uses in
+//t the environment, even though the body of the closure doesn’t strictly need
+//t ownership, you can use the `move` keyword before the parameters. Note that
+//t `move` and the trait of the closure are orthogonal features: whatever the
+//t trait of the closure is, `move` is an option. In other words: `move`
+//t determines how values are captured, the closure trait determines how values
+//t are used.
 
 fn ex_move() {
     let list = vec![1, 2, 3, 4, 5, 6, 7];
@@ -276,7 +312,8 @@ fn ex_move() {
         0
     };
 
-    // ❌ doesn't compile, `list` was moved into the closure
+    // ❌ E0382: `list` was moved into the closure.
+    //
     // println!("After: {:?}", list);
 }
 
@@ -284,6 +321,15 @@ fn ex_fn_move() {
     // This closure uses the `move` keyword,
     // but it is a Fn closure nonetheless.
     let list = vec![1, 2, 3];
+    //t!classify Fn
     let my_fn = move |n| println!("From thread: {:?}", list);
+    //t!layout my_fn
     requires_fn(my_fn);
-}
\ No newline at end of file
+
+    // Without `move` the same closure only captures a
+    // reference to the list: its environment is smaller.
+    let list = vec![1, 2, 3];
+    let my_fn_ref = |n| println!("From thread: {:?}", list);
+    //t!layout my_fn_ref
+    requires_fn(my_fn_ref);
+}
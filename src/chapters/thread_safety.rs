@@ -0,0 +1,73 @@
+// Send/Sync claims of the summary tables in
+// interior_mut_shared_ownership_thread_safety/README.md.
+//
+// The tables are generated from this data, and every claim is proven by
+// the compiler when the docs are built: positive claims are static
+// assertions in a probe that must compile, while negative ones (and the
+// necessity of each condition on `T`) are probes that must not compile.
+
+pub enum Bound {
+    // Implemented if `T` implements all the traits.
+    If(&'static [&'static str]),
+    Never,
+}
+
+pub struct Wrapper {
+    pub ty: &'static str,
+    pub provides: &'static str,
+    pub accessors: &'static str,
+    pub panics: &'static str,
+    pub send: Bound,
+    pub sync: Bound,
+}
+
+pub const INTERIOR_MUTABILITY: &[Wrapper] = &[
+    Wrapper {
+        ty: "Cell<T>",
+        provides: "Values (copies)",
+        accessors: "`.get()`<br>`.set()` <br><sub>to get/set a copy</sub>",
+        panics: "Never",
+        send: Bound::If(&["Send"]),
+        sync: Bound::Never,
+    },
+    Wrapper {
+        ty: "RefCell<T>",
+        provides: "References (&/&mut)",
+        accessors: "`.borrow()`<br>`.borrow_mut()` <br><sub>to get the Ref/RefMut</sub> <br><br> `.deref()`<br>`.deref_mut()`<br> <sub>on the Ref/RefMut</sub>",
+        panics: "Mixed borrows or more <br>than one mutable borrow",
+        send: Bound::If(&["Send"]),
+        sync: Bound::Never,
+    },
+    Wrapper {
+        ty: "Mutex<T>",
+        provides: "References (&/&mut)",
+        accessors: "`.lock()` <br><sub>to get the MutexGuard</sub> <br><br> `.deref()`<br>`.deref_mut()`<br> <sub>on the MutexGuard</sub>",
+        panics: "Never, blocks until the<br> lock is freed (unless <br>poisoned, see notes)",
+        send: Bound::If(&["Send"]),
+        sync: Bound::If(&["Send"]),
+    },
+];
+
+pub const SHARED_OWNERSHIP: &[Wrapper] = &[
+    Wrapper {
+        ty: "Rc<T>",
+        provides: "References (& only)",
+        accessors: "`.deref()` <br><sub>to get the &ref</sub>",
+        panics: "Never",
+        send: Bound::Never,
+        sync: Bound::Never,
+    },
+    Wrapper {
+        ty: "Arc<T>",
+        provides: "References (& only)",
+        accessors: "`.deref()` <br><sub>to get the &ref</sub>",
+        panics: "Never",
+        send: Bound::If(&["Send", "Sync"]),
+        sync: Bound::If(&["Send", "Sync"]),
+    },
+];
+
+// Imports needed to name the wrapper types in the probes.
+pub const PRELUDE: &str = "use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};";
@@ -1,3 +1,10 @@
+//t!meta title: Traits
+//t!meta summary: Shared behavior between types: default implementations, trait bounds, `impl Trait` and blanket implementations.
+//t!meta authors: anBertoli
+//t!meta tags: traits, generics
+//t!meta difficulty: beginner
+//t!meta status: draft
+
 //t # Traits
 
 //t A trait defines the functionality a particular type has and can share with
@@ -15,12 +22,12 @@ pub trait Summary {
 //t Once a trait is defined, you can start implementing it for how many types
 //t you need. If a type implements a trait, it can call the methods defined in
 //t the trait like regular methods. The only difference is that the user must
-//t bring the trait into scope as well as the types. Other crates that depend
-//t on this crate can also bring the `Summary` trait into scope to implement it
-//t on their own types.
+//t bring the trait into scope as well as the types. Other crates that depend on
+//t this crate can also bring the `Summary` trait into scope to implement it on
+//t their own types.
 //t
-//t One restriction to note is that we can implement a trait on a type only
-//t if either the trait or the type, or both, are local to our crate. In other
+//t One restriction to note is that we can implement a trait on a type only if
+//t either the trait or the type, or both, are local to our crate. In other
 //t words, we can’t implement external traits on external types. This is known
 //t as the **orphan rule**: it ensures that other people’s code can’t break your
 //t code and vice versa by creating multiple ambiguous implementations.
@@ -44,7 +51,7 @@ impl Summary for NewsArticle {
 
 fn ex_use_trait() {
     // Call the associated function.
-    println!("tag: {}", NewsArticle::tag());
+    println!("tag: {}", NewsArticle::tag()); // -> tag: 34
 
     let article = NewsArticle {
         title: "some title".to_string(),
@@ -53,19 +60,19 @@ fn ex_use_trait() {
     };
 
     // Call trait method.
-    println!("summary: {}", article.summarize());
+    println!("summary: {}", article.summarize()); // -> summary: some title, by Mark (1)
 }
 
 //t ## Default implementations
 //t
-//t Sometimes it’s useful to have default behavior for some methods in a
-//t trait instead of requiring implementations for all methods on every
-//t type. Then, as we implement the trait on a particular type, we can
-//t keep or override each method’s default behavior.
+//t Sometimes it’s useful to have default behavior for some methods in a trait
+//t instead of requiring implementations for all methods on every type. Then, as
+//t we implement the trait on a particular type, we can keep or override each
+//t method’s default behavior.
 //t
-//t Default implementations can call other methods in the same trait, even
-//t if those other methods don’t have a default implementation. In this way,
-//t a trait can provide a lot of useful functionality and only require
+//t Default implementations can call other methods in the same trait, even if
+//t those other methods don’t have a default implementation. In this way, a
+//t trait can provide a lot of useful functionality and only require
 //t implementors to specify a small part of it.
 
 pub trait Notification {
@@ -78,10 +85,9 @@ pub trait Notification {
     }
 }
 
-//t To implement the trait for a type, we just need to implement the
-//t methods that don't have a default implementation. If we don't override
-//t the methods that have a default implementation, those implementations
-//t will be used.
+//t To implement the trait for a type, we just need to implement the methods
+//t that don't have a default implementation. If we don't override the methods
+//t that have a default implementation, those implementations will be used.
 
 pub struct WhatsappMessage {
     pub sender: String,
@@ -139,7 +145,8 @@ fn ex_use_trait_def() {
 //t Trait can be used as bounds in generic functions and wrapper types to put a
 //t constraint on which type can be uses as a type parameter (`T` in the example
 //t below). In the example, you can instantiate a queue holding, each time, a
-//t different type `T` as long as `T` implements the `Notification` trait.
+//t different type `T` as long as `T` implements the [[traits::Notification]]
+//t trait.
 
 struct NotificationQueue<T: Notification> {
     queue: Vec<T>,
@@ -162,8 +169,14 @@ impl<T: Notification> NotificationQueue<T> {
     }
 }
 
+//t!layout NotificationQueue<WhatsappMessage>
+//t!layout Box<WhatsappMessage>
+//t!layout Option<Box<WhatsappMessage>>
+
 //t For functions, several syntaxes are available to bound the type parameter;
 //t all are equivalent when the generic type is used as a function argument.
+//t Closures are accepted as arguments in the same way, bounded by one of the
+//t [[closures#closure-traits|closure traits]].
 //t
 //t - The simplest syntax is to use the `<T: Notification>` syntax, which means
 //t that the type parameter `T` must implement the `Notification` trait.
@@ -172,14 +185,17 @@ impl<T: Notification> NotificationQueue<T> {
 //t the trait bounds on the generic types. This is usually preferred when the
 //t trait bounds are complex.
 //t
-//t - Finally, another syntax is to use the `impl` keyword in the parameter
-//t list to specify that the argument must implement that trait. The result is
+//t - Finally, another syntax is to use the `impl` keyword in the parameter list
+//t to specify that the argument must implement that trait. The result is
 //t identical to the other 2 syntaxes, but note that it's less flexible.
 
+//t!tabs
+//t!tab Trait bound
 pub fn ex_use_notif<T: Notification>(item: T) {
     println!("{}", item.text());
 }
 
+//t!tab `where` clause
 pub fn ex_use_notif_2<T>(item: T)
 where
     T: Notification,
@@ -187,17 +203,31 @@ where
     println!("{}", item.text());
 }
 
+//t!tab `impl Trait`
 pub fn ex_use_notif_3(item: impl Notification) {
     println!("{}", item.text());
 }
 
+//t Generic functions are monomorphized: the compiler generates a copy of the
+//t function for every concrete type it is used with, so the call to `text()` is
+//t resolved statically. A function taking a `dyn Notification` instead is
+//t compiled once, and calls `text()` through the vtable.
+
+pub fn ex_use_notif_dyn(item: &dyn Notification) {
+    println!("{}", item.text());
+}
+
+//t!asm ex_use_notif::<WhatsappMessage>
+//t!asm ex_use_notif_dyn
+
 //t A type parameter can be subject to multiple trait bounds. In the example
 //t below `T` must be a type that implements both the `Notification` and `Debug`
-//t traits. We skipped the third option here (`impl` keyword), but it would
-//t have used the + as in the other options.
+//t traits. We skipped the third option here (`impl` keyword), but it would have
+//t used the + as in the other options.
 
 use std::fmt::{Debug, Display};
 
+//t!compare
 // ⚠️ Signature starts to be a bit cluttered.
 pub fn ex_use_notif_4<T: Notification + Debug, U: Display>(disclaimer: U, item: T) {
     println!("disclaimer: {}", disclaimer);
@@ -217,8 +247,8 @@ where
 }
 
 //t We can also use the `impl Trait` syntax in the return position to return a
-//t value of some type that implements a trait. By using `impl Notification`
-//t for the return type, we specify that the function returns some type that
+//t value of some type that implements a trait. By using `impl Notification` for
+//t the return type, we specify that the function returns some type that
 //t implements that trait without naming the concrete type. The syntax allows
 //t you to concisely specify that a function returns some type that implements
 //t the given trait without needing to write out a very long type.
@@ -235,7 +265,9 @@ fn ex_ret_notif() -> impl Notification {
 }
 
 fn ex_ret_notif_2(b: bool) -> impl Notification {
-    // ❌ This doesn't compile because the return type is ambiguous.
+    // ❌ E0308: This doesn't compile because the return type is
+    // ambiguous.
+    //
     // if b {
     //     WhatsappMessage {
     //         sender: "Mark".to_string(),
@@ -249,6 +281,7 @@ fn ex_ret_notif_2(b: bool) -> impl Notification {
     // }
 
     // ✅ This compiles because the return type is well-defined.
+    //t!region early_return
     if b {
         return WhatsappMessage {
             sender: "Luke".to_string(),
@@ -260,13 +293,37 @@ fn ex_ret_notif_2(b: bool) -> impl Notification {
         sender: "Mark".to_string(),
         content: "Hello!".to_string(),
     }
+    //t!endregion
 }
 
+//t To return either type, the function can return a trait object instead,
+//t behind a pointer since its size isn't known at compile time.
+
+fn ex_ret_notif_3(b: bool) -> Box<dyn Notification> {
+    //t!region boxed
+    if b {
+        return Box::new(WhatsappMessage {
+            sender: "Luke".to_string(),
+            content: "Hello!".to_string(),
+        });
+    }
+
+    Box::new(SmsMessage {
+        sender: "Simon".to_string(),
+        content: "Hey!".to_string(),
+    })
+    //t!endregion
+}
+
+//t Compared to the early return version, only the returned values change, each
+//t one boxed:
+//t!diff from=early_return to=boxed
+
 //t ### Conditional type implementations
 //t
 //t By using a trait bound within an `impl` block that uses generic type
-//t parameters, we can implement methods conditionally for types the
-//t satisfy the trait bound.
+//t parameters, we can implement methods conditionally for types the satisfy the
+//t trait bound.
 
 use std::ops::Add;
 
@@ -302,20 +359,22 @@ fn ex_cond_impl() {
     println!("x = {}, y = {}", pair.x, pair.y);
     println!("pair.sum() = {}", pair.sum());
 
-    // ❌ new() is available on all Pairs, but sum()
-    // is not available for Pair<&str> since &str
-    // doesn't implement Add.
+    // new() is available on all Pairs...
     let pair_str: Pair<&str> = Pair::new("hello", "world");
     println!("x = {}, y = {}", pair_str.x, pair_str.y);
-    // println!("pair.sum() = {}", pair_str.sum()); doesn't compile
+
+    // ❌ E0599: ...but sum() is not available for
+    // Pair<&str> since &str doesn't implement Add.
+    //
+    // println!("pair.sum() = {}", pair_str.sum());
 }
 
 //t We can also conditionally implement a trait for any type that implements
 //t another trait. Implementations of a trait on any type that satisfies the
 //t trait bounds are called **blanket implementations** and are used extensively
 //t in the Rust standard library. For example, the standard library implements
-//t the `ToString` trait on any type that implements the `Display` trait, roughly
-//t equivalent to `impl<T: Display> ToString for T { ... }`.
+//t the `ToString` trait on any type that implements the `Display` trait,
+//t roughly equivalent to `impl<T: Display> ToString for T { ... }`.
 //t
 //t Because the standard library has this blanket implementation, we can call
 //t the `to_string` method defined by the `ToString` trait "for free" on any
@@ -340,12 +399,12 @@ fn ex_blanket_impl_to_string() {
     println!("{}", message.to_string());
 }
 
-//t Note that the compiler detects and rejects conflicting implementations.
-//t In this case, the compiler detects that the `ToString` trait is already
+//t Note that the compiler detects and rejects conflicting implementations. In
+//t this case, the compiler detects that the `ToString` trait is already
 //t implemented for `WhatsappMessage` and rejects the manual implementation.
 //t
 //t ```rust
-//t // ❌ Conflicting implementation.
+//t // ❌ E0119: Conflicting implementation.
 //t impl ToString for WhatsappMessage {
 //t     fn to_string(&self) -> String {
 //t         self.text()
@@ -363,8 +422,8 @@ fn ex_blanket_impl_to_string() {
 //t //       where T: std::fmt::Display, T: ?Sized;
 //t ```
 
-//t In an equivalent way, we can implement, for example, the `Notification` trait
-//t on every tuple of two items when both implement the `ToString` trait.
+//t In an equivalent way, we can implement, for example, the `Notification`
+//t trait on every tuple of two items when both implement the `ToString` trait.
 
 impl<T: ToString, U: ToString> Notification for (T, U) {
     fn author(&self) -> String {
@@ -381,10 +440,10 @@ fn ex_blanket_impl_notification() {
     // ✅ We didn't implement `Notification` for (&str, &str),
     // but the blanket implementation above automatically
     // implemented it for us.
-    println!("{}", tuple.text());
+    println!("{}", tuple.text()); // -> Hello!
 }
 
 //t ## TODO
 //t - generic traits
 //t - associated items
-//t - trait objects
\ No newline at end of file
+//t - trait objects
@@ -0,0 +1,353 @@
+use crate::annotation;
+use crate::probe;
+
+// A closure is classified by marking its `let` statement with a
+// `//t!classify` directive, optionally followed by the most specific
+// closure trait the prose claims it implements:
+//
+//     //t!classify FnMut
+//     let mut my_fn_mut = || { ... };
+//
+// For every closure trait a probe is generated, passing a reference to
+// the closure to a function bounded by that trait right after its
+// definition, and type-checked with the local rustc.
+//
+// The prose going with the closure must agree with the result too: the
+// comment right before its `let` statement, and the explanation of the
+// annotations in its body, e.g. "The closure is a FnOnce because ...".
+const DIRECTIVE: &str = "//t!classify";
+const TRAITS: [&str; 3] = ["FnOnce", "FnMut", "Fn"];
+
+pub struct Classification {
+    pub line: usize,
+    pub name: String,
+    pub traits: [bool; 3],
+}
+
+pub fn classify(fp: &str, src: &str) -> Result<Vec<Classification>, String> {
+    let lines: Vec<&str> = src.lines().collect();
+
+    let mut out = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let claim = match line.trim().strip_prefix(DIRECTIVE) {
+            Some(claim) => claim.trim(),
+            None => continue,
+        };
+
+        let pos = format!("{}:{}", fp, i + 1);
+        let binding = closure_binding(&lines, i + 1).ok_or(format!(
+            "{}: {} must precede a closure binding",
+            pos, DIRECTIVE
+        ))?;
+
+        let mut traits = [false; 3];
+        for (t, name) in TRAITS.iter().enumerate() {
+            let src = probe_source(&lines, &binding, name);
+            let probe_name = probe::name(fp, &format!("classify_{}_{}", i + 1, name));
+            let (ok, stderr) = probe::check(&probe_name, &src)?;
+            if !ok && t == 0 {
+                return Err(format!(
+                    "{}: closure probe doesn't compile:\n{}",
+                    pos, stderr
+                ));
+            }
+            traits[t] = ok;
+        }
+
+        let actual = traits.iter().rposition(|t| *t).unwrap_or(0);
+        if !claim.is_empty() {
+            let claimed = TRAITS
+                .iter()
+                .position(|t| *t == claim)
+                .ok_or(format!("{}: unknown closure trait `{}`", pos, claim))?;
+            if claimed != actual {
+                return Err(format!(
+                    "{}: `{}` is claimed to be {}, but it is {}",
+                    pos, binding.name, TRAITS[claimed], TRAITS[actual]
+                ));
+            }
+        }
+        for (line, text) in prose(&lines, i, &binding) {
+            let claimed = match claimed_trait(&text) {
+                Some(claimed) => claimed,
+                None => continue,
+            };
+            if claimed != actual {
+                return Err(format!(
+                    "{}:{}: the prose says `{}` is {}, but it is {}",
+                    fp,
+                    line + 1,
+                    binding.name,
+                    TRAITS[claimed],
+                    TRAITS[actual]
+                ));
+            }
+        }
+
+        out.push(Classification {
+            line: i + 1,
+            name: binding.name,
+            traits,
+        });
+    }
+
+    Ok(out)
+}
+
+// Renders the classifications of closures defined in the same code
+// section as a markdown table.
+pub fn table(classes: &[&Classification]) -> Vec<String> {
+    let mut lines = vec![
+        "| Closure | `FnOnce` | `FnMut` | `Fn` |".to_string(),
+        "|:---|:---:|:---:|:---:|".to_string(),
+    ];
+
+    for c in classes {
+        let badges: Vec<&str> = c
+            .traits
+            .iter()
+            .map(|t| if *t { "✅" } else { "🚫" })
+            .collect();
+        lines.push(format!("| `{}` | {} |", c.name, badges.join(" | ")));
+    }
+
+    lines
+}
+
+struct Binding {
+    name: String,
+    arity: usize,
+    // index of the line terminating the `let` statement
+    end: usize,
+}
+
+fn closure_binding(lines: &[&str], i: usize) -> Option<Binding> {
+    let stmt = lines.get(i)?.trim();
+    let stmt = stmt.strip_prefix("let ")?;
+    let stmt = stmt.strip_prefix("mut ").unwrap_or(stmt);
+    let (name, expr) = stmt.split_once('=')?;
+    let name = name.split(':').next()?.trim().to_string();
+
+    let expr = expr.trim();
+    let expr = expr.strip_prefix("move").unwrap_or(expr).trim_start();
+    let params = expr.strip_prefix('|')?;
+    let params = &params[..params.find('|')?];
+    let arity = if params.trim().is_empty() {
+        0
+    } else {
+        split_top_level(params).len()
+    };
+
+    let mut depth: i32 = 0;
+    for (j, line) in lines.iter().enumerate().skip(i) {
+        for c in line.chars() {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        if depth == 0 && line.trim_end().ends_with(';') {
+            return Some(Binding {
+                name,
+                arity,
+                end: j,
+            });
+        }
+    }
+
+    None
+}
+
+// Prose about the closure of a directive, with the index of its first
+// line: the comments of the statements right before the directive, and
+// the explanations of the annotations in the body of the closure.
+fn prose(lines: &[&str], directive: usize, binding: &Binding) -> Vec<(usize, String)> {
+    let mut out = Vec::new();
+
+    let mut comment: Vec<&str> = Vec::new();
+    let mut first = directive;
+    for j in (0..directive).rev() {
+        let line = lines[j].trim();
+        if line.is_empty() || line.starts_with("//t") || line.ends_with('{') {
+            break;
+        }
+        if let Some(text) = line.strip_prefix("//") {
+            comment.insert(0, text.trim());
+            first = j;
+        }
+    }
+    if !comment.is_empty() {
+        out.push((first, comment.join(" ")));
+    }
+
+    for line in lines[directive + 1..=binding.end].iter() {
+        let n = match annotation::marker(line) {
+            Some(n) => n,
+            None => continue,
+        };
+        if let Some(explanation) = explanation(lines, binding.end, n) {
+            out.push(explanation);
+        }
+    }
+
+    out
+}
+
+// First `//t (N) ...` explanation after the given line, with the index of
+// its first line.
+fn explanation(lines: &[&str], after: usize, n: usize) -> Option<(usize, String)> {
+    let item = format!("//t ({}) ", n);
+    let first = (after + 1..lines.len()).find(|j| lines[*j].starts_with(&item))?;
+
+    let mut text = vec![lines[first][item.len()..].trim()];
+    for line in lines[first + 1..].iter() {
+        let rest = match line.strip_prefix("//t ") {
+            Some(rest) if !rest.trim().is_empty() && !rest.starts_with('(') => rest,
+            _ => break,
+        };
+        text.push(rest.trim());
+    }
+    Some((first, text.join(" ")))
+}
+
+// Closure trait the text claims, as in "The closure is a `FnMut`": the
+// first trait named right after "is", an article aside.
+fn claimed_trait(text: &str) -> Option<usize> {
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+        .collect();
+    for (i, word) in words.iter().enumerate() {
+        if !word.eq_ignore_ascii_case("is") {
+            continue;
+        }
+        let mut next = i + 1;
+        if matches!(words.get(next), Some(&"a") | Some(&"an")) {
+            next += 1;
+        }
+        if let Some(t) = words
+            .get(next)
+            .and_then(|w| TRAITS.iter().position(|t| t == w))
+        {
+            return Some(t);
+        }
+    }
+    None
+}
+
+fn split_top_level(params: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut depth = 0;
+    let mut last = 0;
+    for (i, c) in params.char_indices() {
+        match c {
+            '(' | '[' | '<' => depth += 1,
+            ')' | ']' | '>' => depth -= 1,
+            ',' if depth == 0 => {
+                out.push(&params[last..i]);
+                last = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if !params[last..].trim().is_empty() {
+        out.push(&params[last..]);
+    }
+    out
+}
+
+fn probe_source(lines: &[&str], binding: &Binding, bound: &str) -> String {
+    let mut out: Vec<String> = Vec::new();
+    for (j, line) in lines.iter().enumerate() {
+        out.push(line.to_string());
+        if j == binding.end {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            out.push(format!("{}__probe(&{});", indent, binding.name));
+        }
+    }
+
+    let args: Vec<String> = (0..binding.arity).map(|a| format!("A{}", a)).collect();
+    out.push(String::new());
+    out.push(format!(
+        "fn __probe<F, R, {}>(_: &F) where F: {}({}) -> R {{}}",
+        args.join(", "),
+        bound,
+        args.join(", ")
+    ));
+
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims() {
+        let fn_once = Some(0);
+        let fn_mut = Some(1);
+        let fn_ = Some(2);
+        assert_eq!(claimed_trait("❌ The closure is a FnOnce because"), fn_once);
+        assert_eq!(claimed_trait("The closure is an `FnMut`, it"), fn_mut);
+        assert_eq!(claimed_trait("✅The closure is a Fn because"), fn_);
+        assert_eq!(claimed_trait("The closure is FnMut."), fn_mut);
+        assert_eq!(
+            claimed_trait("The closure is a FnMut. It is a subtype of FnOnce"),
+            fn_mut
+        );
+    }
+
+    #[test]
+    fn no_claims() {
+        assert_eq!(claimed_trait("It is a subtype of FnOnce"), None);
+        assert_eq!(claimed_trait("Accepted by a FnOnce bound"), None);
+        assert_eq!(claimed_trait("The closure is a Fn-like thing"), None);
+    }
+
+    fn binding(lines: &[&str], i: usize) -> Binding {
+        closure_binding(lines, i).unwrap()
+    }
+
+    #[test]
+    fn prose_of_a_closure() {
+        let lines = [
+            "fn ex() {",
+            "    // ❌ The closure is FnOnce because it",
+            "    // consumes a value.",
+            "    let s = String::new();",
+            "    //t!classify",
+            "    let f = || {",
+            "        drop(s); // (1)",
+            "    };",
+            "}",
+            "",
+            "//t (1) The closure is a FnOnce, as it",
+            "//t drops `s`.",
+            "//t (2) Something else.",
+        ];
+        let b = binding(&lines, 5);
+        assert_eq!(
+            prose(&lines, 4, &b),
+            vec![
+                (
+                    1,
+                    "❌ The closure is FnOnce because it consumes a value.".to_string()
+                ),
+                (10, "The closure is a FnOnce, as it drops `s`.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn prose_stops_at_blank_lines() {
+        let lines = [
+            "    // Unrelated, the closure is a Fn.",
+            "",
+            "    //t!classify",
+            "    let f = || 1;",
+        ];
+        let b = binding(&lines, 3);
+        assert!(prose(&lines, 2, &b).is_empty());
+    }
+}
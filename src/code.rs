@@ -0,0 +1,242 @@
+use crate::Format;
+use crate::compile::{Doc, Section};
+
+// Attributes of a code section, set by `//t!code` directives right before
+// it. Every renderer maps them to its own syntax.
+//
+//     //t!code title="Closure syntax"
+//     //t!code should_panic start=10 hl=3-5,8
+//
+// Flags only label the code for the reader: it is part of the chapter, so
+// it is compiled with it whatever they say. This rules out `compile_fail`,
+// and any language but Rust: such code goes in fenced blocks of the prose.
+pub const DIRECTIVE: &str = "//t!code ";
+
+const FLAGS: &[&str] = &["ignore", "no_run", "should_panic"];
+
+#[derive(Debug, Clone, Default)]
+pub struct Attrs {
+    pub title: Option<String>,
+    // ignore, no_run, should_panic
+    pub flags: Vec<String>,
+    // number of the first line, when line numbers are shown
+    pub start: Option<usize>,
+    // highlighted lines, as 1-based inclusive ranges within the section
+    pub highlight: Vec<(usize, usize)>,
+}
+
+impl Attrs {
+    // Sets the attributes of a directive, later ones win.
+    pub fn parse(&mut self, args: &str) -> Result<(), String> {
+        for arg in split(args)? {
+            let (key, value) = match arg.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (arg.as_str(), None),
+            };
+
+            match (key, value) {
+                (flag, None) if FLAGS.contains(&flag) => {
+                    if !self.flags.iter().any(|f| f == flag) {
+                        self.flags.push(flag.to_string());
+                    }
+                }
+                ("compile_fail", None) => {
+                    return Err(
+                        "`compile_fail` code doesn't compile, move it to fenced code in the prose"
+                            .to_string(),
+                    );
+                }
+                ("lang", Some(_)) => {
+                    return Err(
+                        "code of the chapter is Rust, other languages go in fenced code in the prose"
+                            .to_string(),
+                    );
+                }
+                ("title", Some(title)) => self.title = Some(title.to_string()),
+                ("start", Some(start)) => {
+                    self.start = Some(
+                        start
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|s| *s > 0)
+                            .ok_or(format!("invalid start line `{}`", start))?,
+                    );
+                }
+                ("hl", Some(ranges)) => self.highlight = parse_ranges(ranges)?,
+                _ => return Err(format!("unknown code attribute `{}`", arg)),
+            }
+        }
+        Ok(())
+    }
+
+    // Highlighted lines must be within the section.
+    pub fn check(&self, len: usize) -> Result<(), String> {
+        match self.highlight.iter().find(|(_, end)| *end > len) {
+            Some((start, end)) => Err(format!(
+                "highlighted lines {}-{} out of range, the code has {} lines",
+                start, end, len
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+// Rewrites the opening fence of every code section for the output format:
+//
+// - markdown: the flags follow the language in the info string, e.g.
+//   ```` ```rust ignore ````, the title is a caption above. Markdown has
+//   no line numbers nor highlighted lines, they are left out;
+// - mdbook: comma separated, as mdBook turns them into HTML classes, e.g.
+//   `language-rust ignore start-10 hl-3-5`, styled by the theme (see
+//   `mdbook.js`), the title is a `code-title`.
+pub fn render(doc: &mut Doc, format: Format) {
+    for s in doc.sections_mut() {
+        let (lines, attrs) = match s {
+            Section::Code { lines, attrs, .. } if !lines.is_empty() => (lines, attrs),
+            _ => continue,
+        };
+
+        lines[0] = format!("```{}", info(attrs, format));
+        if let Some(title) = &attrs.title {
+            match format {
+                Format::Markdown => lines.insert(0, format!("**{}**", title)),
+                // an HTML block lasts until a blank line
+                Format::Mdbook => {
+                    let caption = format!("<p class=\"code-title\">{}</p>", escape(title));
+                    lines.splice(0..0, [caption, String::new()]);
+                }
+            }
+        }
+    }
+}
+
+fn info(attrs: &Attrs, format: Format) -> String {
+    let mut parts = vec!["rust".to_string()];
+    parts.extend(attrs.flags.iter().cloned());
+    let ranges = attrs
+        .highlight
+        .iter()
+        .map(|(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        });
+
+    match format {
+        Format::Markdown => parts.join(" "),
+        Format::Mdbook => {
+            if let Some(start) = attrs.start {
+                parts.push(format!("start-{}", start));
+            }
+            parts.extend(ranges.map(|r| format!("hl-{}", r)));
+            parts.join(",")
+        }
+    }
+}
+
+// `3-5,8` as [(3, 5), (8, 8)].
+fn parse_ranges(s: &str) -> Result<Vec<(usize, usize)>, String> {
+    let mut out = Vec::new();
+    for range in s.split(',') {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let line = |n: &str| n.trim().parse::<usize>().ok().filter(|n| *n > 0);
+        match (line(start), line(end)) {
+            (Some(start), Some(end)) if start <= end => out.push((start, end)),
+            _ => return Err(format!("invalid line range `{}`", range)),
+        }
+    }
+    Ok(out)
+}
+
+// Arguments separated by spaces, values can be quoted: title="Two words".
+fn split(args: &str) -> Result<Vec<String>, String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in args.trim().chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    out.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(format!("unterminated quote in `{}`", args.trim()));
+    }
+    if !current.is_empty() {
+        out.push(current);
+    }
+    Ok(out)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Attrs, String> {
+        let mut attrs = Attrs::default();
+        attrs.parse(args)?;
+        Ok(attrs)
+    }
+
+    #[test]
+    fn attributes() {
+        let attrs = parse("title=\"Two words\"  no_run start=10 hl=3-5,8 no_run").unwrap();
+        assert_eq!(attrs.title.as_deref(), Some("Two words"));
+        assert_eq!(attrs.flags, ["no_run"]);
+        assert_eq!(attrs.start, Some(10));
+        assert_eq!(attrs.highlight, [(3, 5), (8, 8)]);
+    }
+
+    #[test]
+    fn invalid_attributes() {
+        for (args, err) in [
+            ("hl=5-3", "invalid line range `5-3`"),
+            ("hl=0", "invalid line range `0`"),
+            ("hl=1,", "invalid line range ``"),
+            ("start=0", "invalid start line `0`"),
+            (
+                "lang=text",
+                "code of the chapter is Rust, other languages go in fenced code in the prose",
+            ),
+            ("ignore=1", "unknown code attribute `ignore=1`"),
+            ("title=\"a", "unterminated quote in `title=\"a`"),
+            (
+                "compile_fail",
+                "`compile_fail` code doesn't compile, move it to fenced code in the prose",
+            ),
+        ] {
+            assert_eq!(parse(args).unwrap_err(), err, "{}", args);
+        }
+    }
+
+    #[test]
+    fn highlight_in_range() {
+        let attrs = parse("hl=2,4-6").unwrap();
+        assert!(attrs.check(6).is_ok());
+        assert_eq!(
+            attrs.check(5).unwrap_err(),
+            "highlighted lines 4-6 out of range, the code has 5 lines"
+        );
+    }
+
+    #[test]
+    fn info_strings() {
+        let attrs = parse("should_panic start=3 hl=1,2-4").unwrap();
+        assert_eq!(info(&attrs, Format::Markdown), "rust should_panic");
+        assert_eq!(
+            info(&attrs, Format::Mdbook),
+            "rust,should_panic,start-3,hl-1,hl-2-4"
+        );
+    }
+}
@@ -1,50 +1,183 @@
+use crate::annotation;
+use crate::callout::{self, Kind};
+use crate::code::{self, Attrs};
+use crate::meta::{self, Meta};
+use crate::steps;
 use std::fs;
-use std::io::{Read, Write};
+use std::io::Write;
 use std::iter::Peekable;
 use std::str::Lines;
 
 pub struct Doc {
     sections: Vec<Section>,
+    meta: Meta,
 }
 
 impl Doc {
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+
     pub fn write_to_file(&self, fp: &str) -> Result<(), String> {
         let mut f = fs::File::create(fp).map_err(|e| e.to_string())?;
-        for (_, s) in self.sections.iter().enumerate() {
+        f.write_all(self.to_markdown().as_bytes())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn to_markdown(&self) -> String {
+        self.to_markdown_with_lines().0
+    }
+
+    // Markdown of the chapter and the source line of every line of it.
+    // Prose keeps its lines, formatted code and generated content map to
+    // the line of their section.
+    pub fn to_markdown_with_lines(&self) -> (String, Vec<usize>) {
+        let mut out = String::new();
+        let mut map = Vec::new();
+        for s in self.sections.iter() {
             let lines = match s {
-                Section::Comment { lines } => lines,
-                Section::DocComment { lines } => lines,
-                Section::Code { lines } => lines,
+                Section::Directive { .. } => continue,
+                s => s.lines(),
             };
 
-            for l in lines {
-                f.write_all(l.as_bytes()).map_err(|e| e.to_string())?;
-                f.write("\n".as_bytes()).map_err(|e| e.to_string())?;
+            for (i, l) in lines.iter().enumerate() {
+                out.push_str(l);
+                out.push('\n');
+                map.push(match s {
+                    Section::Comment { start, .. } | Section::DocComment { start, .. } => start + i,
+                    s => s.start(),
+                });
             }
 
             if !lines.is_empty() {
-                f.write("\n".as_bytes()).map_err(|e| e.to_string())?;
+                out.push('\n');
+                map.push(map.last().copied().unwrap_or(s.start()));
             }
         }
 
-        Ok(())
+        (out, map)
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn sections_mut(&mut self) -> &mut [Section] {
+        &mut self.sections
+    }
+
+    // Start line of the section containing the given source line.
+    pub fn section_start(&self, line: usize) -> Option<usize> {
+        self.sections
+            .iter()
+            .rev()
+            .map(|s| s.start())
+            .find(|start| *start <= line)
+    }
+
+    pub fn insert(&mut self, idx: usize, section: Section) {
+        self.sections.insert(idx, section);
+    }
+
+    // Inserts generated prose right after the section containing
+    // the given source line, and the explanations of its annotations.
+    pub fn insert_after(&mut self, line: usize, lines: Vec<String>) {
+        let mut idx = self
+            .sections
+            .iter()
+            .rposition(|s| s.start() <= line)
+            .map_or(0, |i| i + 1);
+        while let Some(Section::Annotations { .. }) = self.sections.get(idx) {
+            idx += 1;
+        }
+
+        let section = Section::Generated { start: line, lines };
+        self.sections.insert(idx, section);
     }
 }
 
+// Sections keep the line of the chapter source they start at. Generated
+// sections are inserted by the build (e.g. tables) and have the line of
+// the directive they come from. Callouts are split out of the prose and
+// code sections they're marked in, explanations of annotated code out
+// of the prose following it.
 #[derive(Debug)]
-enum Section {
-    Comment { lines: Vec<String> },
-    DocComment { lines: Vec<String> },
-    Code { lines: Vec<String> },
+pub enum Section {
+    Comment {
+        start: usize,
+        lines: Vec<String>,
+    },
+    DocComment {
+        start: usize,
+        lines: Vec<String>,
+    },
+    Code {
+        start: usize,
+        lines: Vec<String>,
+        attrs: Attrs,
+    },
+    Directive {
+        start: usize,
+        lines: Vec<String>,
+    },
+    Callout {
+        start: usize,
+        kind: Kind,
+        lines: Vec<String>,
+    },
+    Annotations {
+        start: usize,
+        lines: Vec<String>,
+    },
+    Generated {
+        start: usize,
+        lines: Vec<String>,
+    },
 }
 
-pub fn compile(fp: &str) -> Result<Doc, String> {
-    let mut f = fs::File::open(fp).map_err(|e| e.to_string())?;
-    let mut s: String = String::new();
-    f.read_to_string(&mut s).unwrap();
+impl Section {
+    pub fn start(&self) -> usize {
+        match self {
+            Section::Comment { start, .. } => *start,
+            Section::DocComment { start, .. } => *start,
+            Section::Code { start, .. } => *start,
+            Section::Directive { start, .. } => *start,
+            Section::Callout { start, .. } => *start,
+            Section::Annotations { start, .. } => *start,
+            Section::Generated { start, .. } => *start,
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        match self {
+            Section::Comment { lines, .. } => lines,
+            Section::DocComment { lines, .. } => lines,
+            Section::Code { lines, .. } => lines,
+            Section::Directive { lines, .. } => lines,
+            Section::Callout { lines, .. } => lines,
+            Section::Annotations { lines, .. } => lines,
+            Section::Generated { lines, .. } => lines,
+        }
+    }
 
+    pub fn lines_mut(&mut self) -> &mut Vec<String> {
+        match self {
+            Section::Comment { lines, .. } => lines,
+            Section::DocComment { lines, .. } => lines,
+            Section::Code { lines, .. } => lines,
+            Section::Directive { lines, .. } => lines,
+            Section::Callout { lines, .. } => lines,
+            Section::Annotations { lines, .. } => lines,
+            Section::Generated { lines, .. } => lines,
+        }
+    }
+}
+
+// Compiles the source of the chapter `fp`.
+pub fn compile(fp: &str, src: &str) -> Result<Doc, String> {
     let mut sections: Vec<Section> = Vec::new();
-    let mut lines = s.lines().peekable();
+    let mut lines = src.lines().peekable();
+    let mut start = 1;
 
     loop {
         let line = match lines.peek() {
@@ -54,29 +187,96 @@ pub fn compile(fp: &str) -> Result<Doc, String> {
 
         let section: Section;
         if line.starts_with("///") {
-            section = parse_doc_comment(&mut lines)?;
+            section = parse_doc_comment(&mut lines, start)?;
+        } else if steps::is_marker(line) {
+            section = parse_code(&mut lines, start)?;
+        } else if line.starts_with("//t!") {
+            section = parse_directive(&mut lines, start)?;
         } else if line.starts_with("//t") {
-            section = parse_comment(&mut lines)?;
+            section = parse_comment(&mut lines, start)?;
         } else {
-            section = parse_code(&mut lines)?;
+            section = parse_code(&mut lines, start)?;
         }
 
+        start += section.lines().len();
         sections.push(section);
     }
 
+    let sections = steps::extract(sections, fp)?;
+    let mut sections = annotation::extract(callout::extract(sections), fp)?;
+
+    // attributes of the code sections, from the directives before them
+    let mut pending: Option<(usize, Attrs)> = None;
+    for s in sections.iter_mut() {
+        match s {
+            Section::Directive { start, lines } => {
+                for (i, l) in lines.iter().enumerate() {
+                    if let Some(args) = l.strip_prefix(code::DIRECTIVE) {
+                        let (_, attrs) = pending.get_or_insert((*start + i, Attrs::default()));
+                        attrs
+                            .parse(args)
+                            .map_err(|e| format!("{}:{}: {}", fp, *start + i, e))?;
+                    }
+                }
+            }
+            Section::Code { attrs, .. } => {
+                if let Some((_, a)) = pending.take() {
+                    *attrs = a;
+                }
+            }
+            Section::Callout { .. } => {}
+            _ => {
+                if let Some((line, _)) = pending {
+                    return Err(format!("{}:{}: no code after `//t!code`", fp, line));
+                }
+            }
+        }
+    }
+    if let Some((line, _)) = pending {
+        return Err(format!("{}:{}: no code after `//t!code`", fp, line));
+    }
+
     // format code
     for s in sections.iter_mut() {
         match s {
-            Section::Code { lines } => format_code(lines),
+            Section::Code {
+                start,
+                lines,
+                attrs,
+            } => {
+                format_code(lines);
+                attrs
+                    .check(lines.len().saturating_sub(2))
+                    .map_err(|e| format!("{}:{}: {}", fp, start, e))?;
+            }
             Section::Comment { .. } => {}
             Section::DocComment { .. } => {}
+            Section::Directive { .. } => {}
+            Section::Callout { .. } => {}
+            Section::Annotations { .. } => {}
+            Section::Generated { .. } => {}
         }
     }
 
-    Ok(Doc { sections })
+    let mut meta = Meta::default();
+    for s in sections.iter() {
+        if let Section::Directive { start, lines } = s {
+            for (i, l) in lines.iter().enumerate() {
+                let entry = match l.strip_prefix(meta::DIRECTIVE) {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                let (key, value) = entry.split_once(':').unwrap_or((entry, ""));
+                meta.set(key, value)
+                    .map_err(|e| format!("{}:{}: {}", fp, start + i, e))?;
+            }
+        }
+    }
+
+    Ok(Doc { sections, meta })
 }
 
-fn parse_doc_comment(lines: &mut Peekable<Lines>) -> Result<Section, String> {
+fn parse_doc_comment(lines: &mut Peekable<Lines>, start: usize) -> Result<Section, String> {
     let mut ls = Vec::new();
     loop {
         let line = match lines.peek() {
@@ -91,14 +291,15 @@ fn parse_doc_comment(lines: &mut Peekable<Lines>) -> Result<Section, String> {
     if ls.is_empty() {
         Err("empty doc comment".to_string())
     } else {
-        Ok(Section::DocComment { lines: ls })
+        Ok(Section::DocComment { start, lines: ls })
     }
 }
 
-fn parse_comment(lines: &mut Peekable<Lines>) -> Result<Section, String> {
+fn parse_comment(lines: &mut Peekable<Lines>, start: usize) -> Result<Section, String> {
     let mut ls = Vec::new();
     loop {
         let line = match lines.peek() {
+            Some(l) if l.starts_with("//t!") => break,
             Some(l) if l.starts_with("//t") => lines.next().unwrap(),
             _ => break,
         };
@@ -112,20 +313,53 @@ fn parse_comment(lines: &mut Peekable<Lines>) -> Result<Section, String> {
     if ls.is_empty() {
         Err("empty comment".to_string())
     } else {
-        Ok(Section::Comment { lines: ls })
+        Ok(Section::Comment { start, lines: ls })
+    }
+}
+
+// Directives are handled by other passes and
+// don't produce any output on their own.
+fn parse_directive(lines: &mut Peekable<Lines>, start: usize) -> Result<Section, String> {
+    let mut ls = Vec::new();
+    loop {
+        let line = match lines.peek() {
+            Some(l) if steps::is_marker(l) => break,
+            Some(l) if l.starts_with("//t!") => lines.next().unwrap(),
+            _ => break,
+        };
+
+        ls.push(line.trim_end().to_string());
+    }
+
+    if ls.is_empty() {
+        Err("empty directive".to_string())
+    } else {
+        Ok(Section::Directive { start, lines: ls })
     }
 }
 
-fn parse_code<'a, I>(lines: &mut Peekable<I>) -> Result<Section, String>
+fn parse_code<'a, I>(lines: &mut Peekable<I>, start: usize) -> Result<Section, String>
 where
     I: Iterator<Item = &'a str>,
 {
     let mut ls = Vec::new();
+    // steps and their prose are part of the code, see `steps`
+    let mut in_step = false;
     loop {
         let line = match lines.peek() {
+            Some(l) if steps::is_marker(l) => {
+                in_step = true;
+                lines.next().unwrap()
+            }
+            Some(l) if in_step && l.starts_with("//t") && !l.starts_with("//t!") => {
+                lines.next().unwrap()
+            }
             Some(l) if l.starts_with("//t") => break,
             Some(l) if l.starts_with("///") => break,
-            Some(_) => lines.next().unwrap(),
+            Some(_) => {
+                in_step = false;
+                lines.next().unwrap()
+            }
             None => break,
         };
 
@@ -135,11 +369,18 @@ where
     if ls.is_empty() {
         Err("empty code".to_string())
     } else {
-        Ok(Section::Code { lines: ls })
+        Ok(Section::Code {
+            start,
+            lines: ls,
+            attrs: Attrs::default(),
+        })
     }
 }
 
 fn format_code(lines: &mut Vec<String>) {
+    // drop directives nested in code, they are handled by other passes
+    lines.retain(|l| !l.trim_start().starts_with("//t!"));
+
     let mut i = 0;
     let mut prev_empty = false;
     let mut started = false;
@@ -161,10 +402,8 @@ fn format_code(lines: &mut Vec<String>) {
         }
     }
 
-    if !lines.is_empty() {
-        if lines.last().unwrap().is_empty() {
-            lines.pop();
-        };
+    if lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
     }
 
     if !lines.is_empty() {
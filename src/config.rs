@@ -0,0 +1,375 @@
+use std::fs;
+
+pub const CONFIG_FILE: &str = "./handbook.toml";
+
+// The configuration is a small subset of TOML: `[table]` headers and
+// `key = value` pairs, where values are strings, integers, booleans or
+// single-line arrays of them. Keys are stored with their table prefix,
+// e.g. `markdown.preprocessors`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+#[derive(Debug, Default)]
+pub struct Config {
+    values: Vec<(String, Value)>,
+}
+
+impl Config {
+    // A missing file is an empty configuration.
+    pub fn load(fp: &str) -> Result<Config, String> {
+        match fs::read_to_string(fp) {
+            Ok(s) => Config::parse(&s).map_err(|e| format!("{}: {}", fp, e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("reading {}: {}", fp, e)),
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Config, String> {
+        let mut values = Vec::new();
+        let mut table = String::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .ok_or(format!("line {}: invalid table header", i + 1))?;
+                table = name.trim().to_string();
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(format!("line {}: expected `key = value`", i + 1))?;
+            let value = parse_value(value.trim()).map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+            let key = if table.is_empty() {
+                key.trim().to_string()
+            } else {
+                format!("{}.{}", table, key.trim())
+            };
+            values.push((key, value));
+        }
+
+        Ok(Config { values })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_str(&self, key: &str) -> Result<Option<&str>, String> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(format!("config `{}`: expected a string", key)),
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Result<Option<i64>, String> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::Integer(n)) => Ok(Some(*n)),
+            Some(_) => Err(format!("config `{}`: expected an integer", key)),
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, String> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::Bool(b)) => Ok(Some(*b)),
+            Some(_) => Err(format!("config `{}`: expected a boolean", key)),
+        }
+    }
+
+    pub fn get_strings(&self, key: &str) -> Result<Vec<String>, String> {
+        let items = match self.get(key) {
+            None => return Ok(Vec::new()),
+            Some(Value::Array(items)) => items,
+            Some(_) => return Err(format!("config `{}`: expected an array", key)),
+        };
+
+        items
+            .iter()
+            .map(|v| match v {
+                Value::String(s) => Ok(s.clone()),
+                _ => Err(format!("config `{}`: expected an array of strings", key)),
+            })
+            .collect()
+    }
+}
+
+// Appends a string to an array of the configuration source `s`, keeping
+// its layout and comments. The key, and its table, are added if missing.
+pub fn append(s: &str, key: &str, item: &str) -> Result<String, String> {
+    let (table, name) = key.rsplit_once('.').unwrap_or(("", key));
+    let quoted = format!("\"{}\"", item.replace('\\', "\\\\").replace('"', "\\\""));
+
+    let mut lines: Vec<String> = s.lines().map(|l| l.to_string()).collect();
+    let mut current = String::new();
+    let mut last_in_table: Option<usize> = if table.is_empty() { Some(0) } else { None };
+    for (i, line) in lines.iter_mut().enumerate() {
+        let content = strip_comment(line).trim();
+        if let Some(header) = content.strip_prefix('[') {
+            current = header.trim_end_matches(']').trim().to_string();
+            if current == table {
+                last_in_table = Some(i + 1);
+            }
+            continue;
+        }
+        if current != table || content.is_empty() {
+            continue;
+        }
+        last_in_table = Some(i + 1);
+
+        let (k, value) = match content.split_once('=') {
+            Some((k, value)) if k.trim() == name => (k.trim(), value.trim()),
+            _ => continue,
+        };
+        let inner = value
+            .strip_prefix('[')
+            .and_then(|v| v.strip_suffix(']'))
+            .ok_or(format!("config `{}`: expected an array", key))?
+            .trim();
+        let comment = match &line[strip_comment(line).len()..] {
+            "" => String::new(),
+            c => format!(" {}", c),
+        };
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let items = if inner.is_empty() {
+            quoted.clone()
+        } else {
+            format!("{}, {}", inner.trim_end_matches(','), quoted)
+        };
+        *line = format!("{}{} = [{}]{}", indent, k, items, comment);
+        return Ok(lines.join("\n") + "\n");
+    }
+
+    let entry = format!("{} = [{}]", name, quoted);
+    match last_in_table {
+        Some(i) => lines.insert(i, entry),
+        None => {
+            lines.push(String::new());
+            lines.push(format!("[{}]", table));
+            lines.push(entry);
+        }
+    }
+    Ok(lines.join("\n") + "\n")
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_str => escaped = true,
+            '"' => in_str = !in_str,
+            '#' if !in_str => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    if let Some(inner) = s.strip_prefix('[') {
+        let inner = inner.strip_suffix(']').ok_or("unterminated array")?;
+        let items = split_items(inner)?
+            .into_iter()
+            .map(parse_value)
+            .collect::<Result<Vec<Value>, String>>()?;
+        return Ok(Value::Array(items));
+    }
+
+    if let Some(inner) = s.strip_prefix('"') {
+        let inner = inner.strip_suffix('"').ok_or("unterminated string")?;
+        return Ok(Value::String(unescape(inner)?));
+    }
+
+    match s {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => s
+            .replace('_', "")
+            .parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| format!("invalid value `{}`", s)),
+    }
+}
+
+fn split_items(s: &str) -> Result<Vec<&str>, String> {
+    let mut items = Vec::new();
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut last = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_str => escaped = true,
+            '"' => in_str = !in_str,
+            ',' if !in_str => {
+                items.push(s[last..i].trim());
+                last = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if in_str {
+        return Err("unterminated string".to_string());
+    }
+
+    let tail = s[last..].trim();
+    if !tail.is_empty() {
+        items.push(tail);
+    }
+    Ok(items)
+}
+
+fn unescape(s: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            _ => return Err("invalid escape".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = r#"
+# chapters in reading order
+order = ["traits", "closures"]
+
+[markdown]
+toc = true # with a comment
+toc_depth = 1_000
+preprocessors = ["rustfmt", "sed s/#/x/"]
+empty = []
+
+[ mdbook ]
+title = "a \"quoted\" #title\n"
+"#;
+
+    #[test]
+    fn parse() {
+        let c = Config::parse(SRC).unwrap();
+        assert_eq!(c.get_strings("order").unwrap(), ["traits", "closures"]);
+        assert_eq!(c.get_bool("markdown.toc"), Ok(Some(true)));
+        assert_eq!(c.get_int("markdown.toc_depth"), Ok(Some(1000)));
+        assert_eq!(
+            c.get_strings("markdown.preprocessors").unwrap(),
+            ["rustfmt", "sed s/#/x/"]
+        );
+        assert_eq!(c.get("markdown.empty"), Some(&Value::Array(Vec::new())));
+        assert_eq!(c.get_str("mdbook.title"), Ok(Some("a \"quoted\" #title\n")));
+        assert_eq!(c.get("toc"), None);
+        assert_eq!(c.get_strings("missing"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn last_value_wins() {
+        let c = Config::parse("a = 1\na = 2\n").unwrap();
+        assert_eq!(c.get_int("a"), Ok(Some(2)));
+    }
+
+    #[test]
+    fn wrong_types() {
+        let c = Config::parse("s = \"x\"\nn = 1\nl = [1]\n").unwrap();
+        assert_eq!(
+            c.get_int("s"),
+            Err("config `s`: expected an integer".to_string())
+        );
+        assert_eq!(
+            c.get_bool("n"),
+            Err("config `n`: expected a boolean".to_string())
+        );
+        assert_eq!(
+            c.get_str("l"),
+            Err("config `l`: expected a string".to_string())
+        );
+        assert_eq!(
+            c.get_strings("n"),
+            Err("config `n`: expected an array".to_string())
+        );
+        assert_eq!(
+            c.get_strings("l"),
+            Err("config `l`: expected an array of strings".to_string())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        for (src, err) in [
+            ("[table\n", "line 1: invalid table header"),
+            ("\nkey\n", "line 2: expected `key = value`"),
+            ("a = \"x\n", "line 1: unterminated string"),
+            ("a = [1, 2\n", "line 1: unterminated array"),
+            ("a = [\"x]\n", "line 1: unterminated string"),
+            ("a = \"\\x\"\n", "line 1: invalid escape"),
+            ("a = yes\n", "line 1: invalid value `yes`"),
+        ] {
+            assert_eq!(Config::parse(src).unwrap_err(), err, "{}", src);
+        }
+    }
+
+    #[test]
+    fn append_to_existing_array() {
+        let out = append(SRC, "markdown.preprocessors", "a \"b\"").unwrap();
+        assert!(
+            out.contains(r#"preprocessors = ["rustfmt", "sed s/#/x/", "a \"b\""]"#),
+            "{}",
+            out
+        );
+        let c = Config::parse(&out).unwrap();
+        assert_eq!(
+            c.get_strings("markdown.preprocessors").unwrap()[2],
+            "a \"b\""
+        );
+
+        let out = append("[t]\nk = [] # keep\n", "t.k", "x").unwrap();
+        assert_eq!(out, "[t]\nk = [\"x\"] # keep\n");
+    }
+
+    #[test]
+    fn append_new_keys() {
+        let out = append("[t]\na = 1\n\n[u]\n", "t.k", "x").unwrap();
+        assert_eq!(out, "[t]\na = 1\nk = [\"x\"]\n\n[u]\n");
+
+        let out = append("[u]\n", "t.k", "x").unwrap();
+        assert_eq!(out, "[u]\n\n[t]\nk = [\"x\"]\n");
+
+        let out = append("[u]\na = 1\n", "k", "x").unwrap();
+        assert_eq!(out, "k = [\"x\"]\n[u]\na = 1\n");
+
+        assert_eq!(
+            append("[t]\nk = 1\n", "t.k", "x"),
+            Err("config `t.k`: expected an array".to_string())
+        );
+    }
+}
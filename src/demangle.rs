@@ -0,0 +1,533 @@
+// Demangling of the Rust symbols in a line of assembly, both in the legacy
+// scheme (`_ZN...E`) and in v0 (`_R...`). Hashes and crate disambiguators
+// are left out, so that the output doesn't depend on the toolchain.
+// Symbols that can't be demangled are left as they are.
+pub fn line(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let len = rest.find(|c| !is_symbol_char(c)).unwrap_or(rest.len());
+        if len == 0 {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let token = &rest[..len];
+        match symbol(token) {
+            Some(demangled) => out.push_str(&demangled),
+            None => out.push_str(token),
+        }
+        rest = &rest[len..];
+    }
+    out
+}
+
+pub fn symbol(s: &str) -> Option<String> {
+    if let Some(rest) = s.strip_prefix("_ZN") {
+        return legacy(rest);
+    }
+    let rest = s.strip_prefix("_R")?;
+    let mut p = Parser {
+        s: rest.as_bytes(),
+        pos: 0,
+        depth: 0,
+    };
+    // encoding version
+    while p.peek().is_some_and(|c| c.is_ascii_digit()) {
+        p.pos += 1;
+    }
+    let mut out = String::new();
+    p.path(&mut out, true)?;
+    Some(out)
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '.'
+}
+
+fn legacy(mut rest: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let len: usize = rest[..digits].parse().ok()?;
+        let ident = rest.get(digits..digits + len)?;
+        rest = &rest[digits + len..];
+        parts.push(ident);
+    }
+    // suffixes such as `.llvm.<n>` are left out
+    if !rest.starts_with('E') || !(rest.len() == 1 || rest[1..].starts_with('.')) {
+        return None;
+    }
+
+    if parts
+        .last()
+        .and_then(|p| p.strip_prefix('h'))
+        .is_some_and(|h| h.len() == 16 && h.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        parts.pop();
+    }
+    let parts: Option<Vec<String>> = parts.into_iter().map(unescape).collect();
+    Some(parts?.join("::"))
+}
+
+// Legacy identifiers escape non-identifier characters, e.g. `$LT$` for `<`
+// and `..` for `::`, and a leading `$` with an underscore.
+fn unescape(ident: &str) -> Option<String> {
+    let mut rest = ident.strip_prefix("_$").map_or(ident, |_| &ident[1..]);
+    let mut out = String::new();
+    while let Some(c) = rest.chars().next() {
+        if let Some(dots) = rest.strip_prefix("..") {
+            out.push_str("::");
+            rest = dots;
+        } else if c == '$' {
+            let end = rest[1..].find('$')? + 1;
+            let decoded = match &rest[1..end] {
+                "SP" => '@',
+                "BP" => '*',
+                "RF" => '&',
+                "LT" => '<',
+                "GT" => '>',
+                "LP" => '(',
+                "RP" => ')',
+                "C" => ',',
+                escape => {
+                    let hex = escape.strip_prefix('u')?;
+                    char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+                }
+            };
+            out.push(decoded);
+            rest = &rest[end + 1..];
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    Some(out)
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    // of backrefs, so that malformed symbols can't loop forever
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn base62(&mut self) -> Option<usize> {
+        if self.eat(b'_') {
+            return Some(0);
+        }
+        let mut n: usize = 0;
+        loop {
+            let c = self.next()?;
+            let digit = match c {
+                b'0'..=b'9' => c - b'0',
+                b'a'..=b'z' => c - b'a' + 10,
+                b'A'..=b'Z' => c - b'A' + 36,
+                b'_' => return n.checked_add(1),
+                _ => return None,
+            };
+            n = n.checked_mul(62)?.checked_add(digit as usize)?;
+        }
+    }
+
+    fn disambiguator(&mut self) -> Option<usize> {
+        match self.eat(b's') {
+            true => self.base62().map(|n| n + 1),
+            false => Some(0),
+        }
+    }
+
+    fn identifier(&mut self) -> Option<(usize, &str)> {
+        let dis = self.disambiguator()?;
+        if self.eat(b'u') {
+            // punycode
+            return None;
+        }
+        let start = self.pos;
+        // lengths have no leading zeros, `0` is a length on its own
+        if !self.eat(b'0') {
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.pos += 1;
+            }
+        }
+        let len: usize = std::str::from_utf8(&self.s[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()?;
+        self.eat(b'_');
+        let end = self.pos.checked_add(len)?;
+        let ident = std::str::from_utf8(self.s.get(self.pos..end)?).ok()?;
+        self.pos = end;
+        Some((dis, ident))
+    }
+
+    // Runs `f` at the position of a backref, coming back after it.
+    fn backref<F>(&mut self, out: &mut String, f: F) -> Option<()>
+    where
+        F: FnOnce(&mut Self, &mut String) -> Option<()>,
+    {
+        let target = self.base62()?;
+        if target >= self.pos || self.depth > 64 {
+            return None;
+        }
+        let back = self.pos;
+        self.pos = target;
+        self.depth += 1;
+        let res = f(self, out);
+        self.depth -= 1;
+        self.pos = back;
+        res
+    }
+
+    // Generic arguments are written as `::<...>` in value paths.
+    fn path(&mut self, out: &mut String, in_value: bool) -> Option<()> {
+        match self.next()? {
+            b'C' => {
+                let (_, name) = self.identifier()?;
+                out.push_str(name);
+            }
+            b'N' => {
+                let ns = self.next()?;
+                self.path(out, in_value)?;
+                let (dis, name) = self.identifier()?;
+                match ns {
+                    b'C' => out.push_str(&format!("::{{closure#{}}}", dis)),
+                    b'S' => out.push_str(&format!("::{{shim:{}#{}}}", name, dis)),
+                    b'A'..=b'Z' => out.push_str(&format!("::{{{}#{}}}", ns as char, dis)),
+                    _ => {
+                        out.push_str("::");
+                        out.push_str(name);
+                    }
+                }
+            }
+            b'M' => {
+                self.disambiguator()?;
+                self.skip_path()?;
+                out.push('<');
+                self.ty(out)?;
+                out.push('>');
+            }
+            b'X' => {
+                self.disambiguator()?;
+                self.skip_path()?;
+                out.push('<');
+                self.ty(out)?;
+                out.push_str(" as ");
+                self.path(out, false)?;
+                out.push('>');
+            }
+            b'Y' => {
+                out.push('<');
+                self.ty(out)?;
+                out.push_str(" as ");
+                self.path(out, false)?;
+                out.push('>');
+            }
+            b'I' => {
+                self.path(out, in_value)?;
+                if in_value {
+                    out.push_str("::");
+                }
+                out.push('<');
+                let mut first = true;
+                while !self.eat(b'E') {
+                    if !first {
+                        out.push_str(", ");
+                    }
+                    first = false;
+                    self.generic_arg(out)?;
+                }
+                out.push('>');
+            }
+            b'B' => self.backref(out, |p, out| p.path(out, in_value))?,
+            _ => return None,
+        }
+        Some(())
+    }
+
+    // Impl paths only disambiguate, they aren't shown.
+    fn skip_path(&mut self) -> Option<()> {
+        let mut ignored = String::new();
+        self.path(&mut ignored, false)
+    }
+
+    fn generic_arg(&mut self, out: &mut String) -> Option<()> {
+        if self.eat(b'L') {
+            self.base62()?;
+            out.push_str("'_");
+            return Some(());
+        }
+        if self.eat(b'K') {
+            return self.konst(out);
+        }
+        self.ty(out)
+    }
+
+    fn ty(&mut self, out: &mut String) -> Option<()> {
+        let basic = match self.peek()? {
+            b'a' => "i8",
+            b'b' => "bool",
+            b'c' => "char",
+            b'd' => "f64",
+            b'e' => "str",
+            b'f' => "f32",
+            b'h' => "u8",
+            b'i' => "isize",
+            b'j' => "usize",
+            b'l' => "i32",
+            b'm' => "u32",
+            b'n' => "i128",
+            b'o' => "u128",
+            b's' => "i16",
+            b't' => "u16",
+            b'u' => "()",
+            b'v' => "...",
+            b'x' => "i64",
+            b'y' => "u64",
+            b'z' => "!",
+            b'p' => "_",
+            _ => "",
+        };
+        if !basic.is_empty() {
+            self.pos += 1;
+            out.push_str(basic);
+            return Some(());
+        }
+
+        match self.next()? {
+            b'R' | b'Q' => {
+                out.push('&');
+                if self.s.get(self.pos - 1) == Some(&b'Q') {
+                    out.push_str("mut ");
+                }
+                if self.eat(b'L') {
+                    self.base62()?;
+                }
+                self.ty(out)?;
+            }
+            b'P' => {
+                out.push_str("*const ");
+                self.ty(out)?;
+            }
+            b'O' => {
+                out.push_str("*mut ");
+                self.ty(out)?;
+            }
+            b'A' => {
+                out.push('[');
+                self.ty(out)?;
+                out.push_str("; ");
+                self.konst(out)?;
+                out.push(']');
+            }
+            b'S' => {
+                out.push('[');
+                self.ty(out)?;
+                out.push(']');
+            }
+            b'T' => {
+                out.push('(');
+                let mut n = 0;
+                while !self.eat(b'E') {
+                    if n > 0 {
+                        out.push_str(", ");
+                    }
+                    self.ty(out)?;
+                    n += 1;
+                }
+                if n == 1 {
+                    out.push(',');
+                }
+                out.push(')');
+            }
+            b'D' => {
+                out.push_str("dyn ");
+                if self.eat(b'G') {
+                    self.base62()?;
+                }
+                let mut first = true;
+                while !self.eat(b'E') {
+                    if !first {
+                        out.push_str(" + ");
+                    }
+                    first = false;
+                    self.dyn_trait(out)?;
+                }
+                // lifetime
+                if !self.eat(b'L') {
+                    return None;
+                }
+                self.base62()?;
+            }
+            b'B' => self.backref(out, |p, out| p.ty(out))?,
+            b'C' | b'N' | b'M' | b'X' | b'Y' | b'I' => {
+                self.pos -= 1;
+                self.path(out, false)?;
+            }
+            // function pointers aren't needed so far
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn dyn_trait(&mut self, out: &mut String) -> Option<()> {
+        self.path(out, false)?;
+        let mut assoc = Vec::new();
+        while self.eat(b'p') {
+            let (_, name) = self.identifier()?;
+            let mut ty = format!("{} = ", name);
+            self.ty(&mut ty)?;
+            assoc.push(ty);
+        }
+        if !assoc.is_empty() {
+            // after the generic arguments of the trait, if any
+            match out.ends_with('>') {
+                true => {
+                    out.pop();
+                    out.push_str(", ");
+                }
+                false => out.push('<'),
+            }
+            out.push_str(&assoc.join(", "));
+            out.push('>');
+        }
+        Some(())
+    }
+
+    fn konst(&mut self, out: &mut String) -> Option<()> {
+        if self.eat(b'p') {
+            out.push('_');
+            return Some(());
+        }
+        if self.eat(b'B') {
+            return self.backref(out, |p, out| p.konst(out));
+        }
+        let ty = self.next()?;
+        let negative = self.eat(b'n');
+        let start = self.pos;
+        while self.peek()? != b'_' {
+            self.pos += 1;
+        }
+        let hex = std::str::from_utf8(&self.s[start..self.pos]).ok()?;
+        self.pos += 1;
+        let value = match hex.is_empty() {
+            true => 0,
+            false => u128::from_str_radix(hex, 16).ok()?,
+        };
+        match ty {
+            b'b' => out.push_str(if value == 0 { "false" } else { "true" }),
+            b'c' => out.push(char::from_u32(value as u32)?),
+            _ => {
+                if negative {
+                    out.push('-');
+                }
+                out.push_str(&value.to_string());
+            }
+        }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_symbols() {
+        assert_eq!(
+            symbol("_ZN6traits12ex_use_notif17h0c54d1bfee1d702fE").as_deref(),
+            Some("traits::ex_use_notif")
+        );
+        assert_eq!(
+            symbol(
+                "_ZN4core3ptr44drop_in_place$LT$traits..WhatsappMessage$GT$17h28ad9e3ddd83ac5aE"
+            )
+            .as_deref(),
+            Some("core::ptr::drop_in_place<traits::WhatsappMessage>")
+        );
+        assert_eq!(
+            symbol("_ZN4core3fmt3num52_$LT$impl$u20$core..fmt..Debug$u20$for$u20$usize$GT$3fmt17h0123456789abcdefE")
+                .as_deref(),
+            Some("core::fmt::num::<impl core::fmt::Debug for usize>::fmt")
+        );
+    }
+
+    #[test]
+    fn v0_paths() {
+        assert_eq!(
+            symbol("_RNvCsfLfy6EI15iL_7___rustc14___rust_dealloc").as_deref(),
+            Some("__rustc::__rust_dealloc")
+        );
+        assert_eq!(
+            symbol("_RNvNtNtCsjrHSEGnQ3l9_3std2io5stdio6__print").as_deref(),
+            Some("std::io::stdio::_print")
+        );
+    }
+
+    #[test]
+    fn v0_trait_impls_and_backrefs() {
+        assert_eq!(
+            symbol(
+                "_RNvXs4_NtCslNYArtu3iFV_5alloc6stringNtB5_6StringNtNtCsgEmfK2I1SDS_4core5clone5Clone5clone"
+            )
+            .as_deref(),
+            Some("<alloc::string::String as core::clone::Clone>::clone")
+        );
+    }
+
+    #[test]
+    fn v0_generics_and_closures() {
+        // std::ptr::drop_in_place::<(u8, &mut str)>
+        assert_eq!(
+            symbol("_RINvNtCs1234_4core3ptr13drop_in_placeThQeEEB4_").as_deref(),
+            Some("core::ptr::drop_in_place::<(u8, &mut str)>")
+        );
+        assert_eq!(
+            symbol("_RNCNvCs1234_5crate4main0B3_").as_deref(),
+            Some("crate::main::{closure#0}")
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(symbol("_ZN6traits"), None);
+        assert_eq!(symbol("_RNvB_3foo"), None);
+        assert_eq!(symbol("_RNvCs1234_5cr"), None);
+        assert_eq!(symbol("_Unwind_Resume"), None);
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            line(
+                "    call qword ptr [rip + _RNvCsfLfy6EI15iL_7___rustc14___rust_dealloc@GOTPCREL]"
+            ),
+            "    call qword ptr [rip + __rustc::__rust_dealloc@GOTPCREL]"
+        );
+        assert_eq!(
+            line("    call _Unwind_Resume@PLT"),
+            "    call _Unwind_Resume@PLT"
+        );
+        assert_eq!(line("    mov rax, __R"), "    mov rax, __R");
+    }
+}
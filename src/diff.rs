@@ -0,0 +1,228 @@
+// Changes from a version of some code to another, both kept (and compiled)
+// in the chapter source as named regions, inside functions too:
+//
+//     //t!region single
+//     ...
+//     //t!endregion
+//
+//     //t!diff from=single to=early_return
+//
+// The line diff is rendered at the directive as a `diff` code block.
+const REGION: &str = "//t!region ";
+const END: &str = "//t!endregion";
+const DIRECTIVE: &str = "//t!diff ";
+
+pub struct Diff {
+    pub line: usize,
+    // unified, without hunk headers: ` `, `-` or `+` and the line
+    pub lines: Vec<String>,
+}
+
+pub fn extract(fp: &str, src: &str) -> Result<Vec<Diff>, String> {
+    let lines: Vec<&str> = src.lines().collect();
+    let regions = regions(fp, &lines)?;
+
+    let mut out = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let args = match line.trim().strip_prefix(DIRECTIVE) {
+            Some(args) => args,
+            None => continue,
+        };
+
+        let pos = format!("{}:{}", fp, i + 1);
+        let (mut from, mut to) = (None, None);
+        for arg in args.split_whitespace() {
+            match arg.split_once('=') {
+                Some(("from", name)) => from = Some(name),
+                Some(("to", name)) => to = Some(name),
+                _ => return Err(format!("{}: unknown diff argument `{}`", pos, arg)),
+            }
+        }
+        let region = |name: Option<&str>, arg: &str| {
+            let name = name.ok_or(format!("{}: missing `{}=<region>`", pos, arg))?;
+            regions
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, lines)| lines)
+                .ok_or(format!("{}: unknown region `{}`", pos, name))
+        };
+
+        out.push(Diff {
+            line: i + 1,
+            lines: diff(region(from, "from")?, region(to, "to")?),
+        });
+    }
+
+    Ok(out)
+}
+
+pub fn render(diffs: &[&Diff]) -> Vec<String> {
+    let mut out = Vec::new();
+    for d in diffs {
+        if !out.is_empty() {
+            out.push(String::new());
+        }
+        out.push("```diff".to_string());
+        out.extend(d.lines.iter().cloned());
+        out.push("```".to_string());
+    }
+    out
+}
+
+// Named regions, dedented, without the directives in them. Regions can
+// be nested.
+fn regions(fp: &str, lines: &[&str]) -> Result<Vec<(String, Vec<String>)>, String> {
+    let mut out: Vec<(String, Vec<String>)> = Vec::new();
+    // (name, line) of the open regions
+    let mut open: Vec<(String, usize)> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let t = line.trim();
+        if let Some(name) = t.strip_prefix(REGION) {
+            let name = name.trim().to_string();
+            if out.iter().any(|(n, _)| *n == name) || open.iter().any(|(n, _)| *n == name) {
+                return Err(format!("{}:{}: duplicate region `{}`", fp, i + 1, name));
+            }
+            open.push((name, i + 1));
+            continue;
+        }
+        if t == END {
+            let (name, start) =
+                open.pop()
+                    .ok_or(format!("{}:{}: `{}` outside of a region", fp, i + 1, END))?;
+            let code: Vec<&str> = lines[start..i]
+                .iter()
+                .filter(|l| !l.trim_start().starts_with("//t!"))
+                .copied()
+                .collect();
+            out.push((name, dedent(&code)));
+        }
+    }
+
+    match open.last() {
+        Some((name, line)) => Err(format!("{}:{}: unterminated region `{}`", fp, line, name)),
+        None => Ok(out),
+    }
+}
+
+fn dedent(lines: &[&str]) -> Vec<String> {
+    let indent = lines
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|l| l.get(indent..).unwrap_or("").trim_end().to_string())
+        .collect()
+}
+
+// Line diff through the longest common subsequence. Removed lines come
+// before the added ones.
+fn diff(a: &[String], b: &[String]) -> Vec<String> {
+    // lcs[i][j]: length of the LCS of a[i..] and b[j..]
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let line = |op: char, l: &str| format!("{}{}", op, l).trim_end().to_string();
+    let mut out = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out.push(line(' ', &a[i]));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push(line('-', &a[i]));
+            i += 1;
+        } else {
+            out.push(line('+', &b[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(src: &str) -> Vec<String> {
+        src.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn line_diff() {
+        assert_eq!(
+            diff(&lines("a\nb\nc\nd"), &lines("a\nx\nc\nd\ne")),
+            [" a", "-b", "+x", " c", " d", "+e"]
+        );
+        assert_eq!(diff(&lines("a\n\nb"), &lines("\nb")), ["-a", "", " b"]);
+        assert!(diff(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn nested_regions() {
+        let src = "\
+fn a() {
+    //t!region outer
+    let x = 1;
+    //t!region inner
+    if x > 0 {
+        println!();
+    }
+    //t!endregion
+    //t!endregion
+}";
+        let lines: Vec<&str> = src.lines().collect();
+        let regions = regions("x.rs", &lines).unwrap();
+        assert_eq!(regions[0].0, "inner");
+        assert_eq!(regions[0].1, ["if x > 0 {", "    println!();", "}"]);
+        assert_eq!(regions[1].0, "outer");
+        assert_eq!(
+            regions[1].1,
+            ["let x = 1;", "if x > 0 {", "    println!();", "}"]
+        );
+    }
+
+    #[test]
+    fn region_errors() {
+        for (src, err) in [
+            (
+                "//t!region a\n//t!endregion\n//t!region a\n//t!endregion",
+                "x.rs:3: duplicate region `a`",
+            ),
+            (
+                "//t!endregion",
+                "x.rs:1: `//t!endregion` outside of a region",
+            ),
+            ("//t!region a\nfn a() {}", "x.rs:1: unterminated region `a`"),
+        ] {
+            let lines: Vec<&str> = src.lines().collect();
+            assert_eq!(regions("x.rs", &lines).unwrap_err(), err);
+        }
+    }
+
+    #[test]
+    fn directive_errors() {
+        for (directive, err) in [
+            ("from=a", "x.rs:4: missing `to=<region>`"),
+            ("from=a to=b", "x.rs:4: unknown region `b`"),
+            ("from=a by=b", "x.rs:4: unknown diff argument `by=b`"),
+        ] {
+            let src = format!(
+                "//t!region a\nfn a() {{}}\n//t!endregion\n//t!diff {}\n",
+                directive
+            );
+            assert_eq!(extract("x.rs", &src).err().unwrap(), err);
+        }
+    }
+}
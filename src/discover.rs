@@ -0,0 +1,177 @@
+use crate::config::Config;
+use std::fs;
+
+pub const CHAPTERS_DIR: &str = "./src/chapters";
+pub const MODULES_FILE: &str = "./src/chapters.rs";
+
+// Chapters in reading order, as (name, path): every `.rs` file under the
+// chapters directory, except the support modules (`chapters.support`),
+// and the given markdown chapters. Chapters listed in `chapters.order`
+// come first, the others follow in alphabetical order.
+pub fn chapters(
+    config: &Config,
+    markdown: &[(&str, &str)],
+) -> Result<Vec<(String, String)>, String> {
+    order(config, sources()?, markdown)
+}
+
+fn order(
+    config: &Config,
+    sources: Vec<String>,
+    markdown: &[(&str, &str)],
+) -> Result<Vec<(String, String)>, String> {
+    let support = config.get_strings("chapters.support")?;
+    let mut found: Vec<(String, String)> = sources
+        .into_iter()
+        .filter(|name| !support.contains(name))
+        .map(|name| {
+            let fp = format!("{}/{}.rs", CHAPTERS_DIR, name);
+            (name, fp)
+        })
+        .collect();
+    found.extend(
+        markdown
+            .iter()
+            .map(|(n, fp)| (n.to_string(), fp.to_string())),
+    );
+
+    let order = config.get_strings("chapters.order")?;
+    found.sort_by_key(|(name, _)| {
+        let pos = order.iter().position(|o| o == name).unwrap_or(order.len());
+        (pos, name.clone())
+    });
+    Ok(found)
+}
+
+// Inconsistencies between the chapter files, the module declarations in
+// `src/chapters.rs` and the configuration. A chapter that is rendered but
+// not declared is never type-checked, a declared module that isn't found
+// is never rendered.
+pub fn check(config: &Config, markdown: &[(&str, &str)]) -> Result<Vec<String>, String> {
+    let content =
+        fs::read_to_string(MODULES_FILE).map_err(|e| format!("reading {}: {}", MODULES_FILE, e))?;
+    inconsistencies(config, &sources()?, &modules(&content), markdown)
+}
+
+fn inconsistencies(
+    config: &Config,
+    sources: &[String],
+    modules: &[String],
+    markdown: &[(&str, &str)],
+) -> Result<Vec<String>, String> {
+    let mut warnings = Vec::new();
+
+    for name in sources.iter() {
+        if !modules.contains(name) {
+            warnings.push(format!(
+                "{}/{}.rs: not declared in {}, it's never type-checked",
+                CHAPTERS_DIR, name, MODULES_FILE
+            ));
+        }
+    }
+    for name in modules.iter() {
+        if !sources.contains(name) {
+            warnings.push(format!(
+                "{}: module `{}` has no file under {}, it's never rendered",
+                MODULES_FILE, name, CHAPTERS_DIR
+            ));
+        }
+    }
+
+    let known = |name: &String| sources.contains(name) || markdown.iter().any(|(n, _)| n == name);
+    for key in ["chapters.order", "chapters.support"] {
+        for name in config.get_strings(key)? {
+            if !known(&name) {
+                warnings.push(format!("config `{}`: unknown chapter `{}`", key, name));
+            }
+        }
+    }
+    Ok(warnings)
+}
+
+// Names of the `.rs` files under the chapters directory, sorted.
+fn sources() -> Result<Vec<String>, String> {
+    let entries =
+        fs::read_dir(CHAPTERS_DIR).map_err(|e| format!("reading {}: {}", CHAPTERS_DIR, e))?;
+
+    let mut names = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().is_some_and(|e| e == "rs")
+            && let Some(stem) = path.file_stem()
+        {
+            names.push(stem.to_string_lossy().to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+// Modules declared as `mod name;` (with any visibility) in a file.
+fn modules(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|l| {
+            let l = l.trim();
+            let rest = match l.split_once("mod ") {
+                Some((vis, rest)) if vis.is_empty() || vis.starts_with("pub") => rest,
+                _ => return None,
+            };
+            rest.strip_suffix(';').map(|n| n.trim().to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn reading_order() {
+        let config =
+            Config::parse("[chapters]\norder = [\"traits\", \"intro\"]\nsupport = [\"support\"]\n")
+                .unwrap();
+        let sources = names(&["closures", "support", "traits", "async"]);
+        let found = order(&config, sources, &[("intro", "docs/intro.md")]).unwrap();
+        assert_eq!(
+            found,
+            [
+                ("traits", "./src/chapters/traits.rs"),
+                ("intro", "docs/intro.md"),
+                ("async", "./src/chapters/async.rs"),
+                ("closures", "./src/chapters/closures.rs"),
+            ]
+            .map(|(n, fp)| (n.to_string(), fp.to_string()))
+        );
+    }
+
+    #[test]
+    fn declared_modules() {
+        let content = "pub mod closures;\nmod support;\npub(crate) mod traits;\n\n#[cfg(test)]\nmod tests {\n    use super::*;\n}\n";
+        assert_eq!(modules(content), names(&["closures", "support", "traits"]));
+    }
+
+    #[test]
+    fn inconsistent_chapters() {
+        let config = Config::parse("[chapters]\norder = [\"intro\", \"gone\"]\n").unwrap();
+        let warnings = inconsistencies(
+            &config,
+            &names(&["closures", "draft"]),
+            &names(&["closures", "removed"]),
+            &[("intro", "docs/intro.md")],
+        )
+        .unwrap();
+        assert_eq!(
+            warnings,
+            [
+                "./src/chapters/draft.rs: not declared in ./src/chapters.rs, it's never type-checked",
+                "./src/chapters.rs: module `removed` has no file under ./src/chapters, it's never rendered",
+                "config `chapters.order`: unknown chapter `gone`",
+            ]
+        );
+    }
+}
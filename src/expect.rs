@@ -0,0 +1,181 @@
+use crate::probe;
+
+// Output assertions are written as a trailing `// -> <output>` comment on a
+// single-line print!/println! statement, e.g.:
+//
+//     println!("{}", add_from_env(2)); // -> 5
+//
+// The chapter is compiled as a standalone program where every annotated
+// statement is turned into an assertion, and each function containing one
+// is called from a generated main.
+const MARKER: &str = "// -> ";
+
+pub fn verify(fp: &str, src: &str) -> Result<(), String> {
+    let lines: Vec<&str> = src.lines().collect();
+    let callable = probe::callable_fns(&lines);
+
+    let mut out: Vec<String> = Vec::new();
+    let mut fns: Vec<String> = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let (code, want) = match assertion(line) {
+            Some(assertion) => assertion,
+            None => {
+                out.push(line.to_string());
+                continue;
+            }
+        };
+
+        let args = print_args(code).ok_or(format!(
+            "{}:{}: output assertion must follow a single-line print!/println!",
+            fp,
+            i + 1
+        ))?;
+
+        let name = match &callable[i] {
+            Some(name) => name.clone(),
+            None => {
+                return Err(format!(
+                    "{}:{}: output assertion outside of a function without arguments",
+                    fp,
+                    i + 1
+                ));
+            }
+        };
+        if !fns.contains(&name) {
+            fns.push(name);
+        }
+
+        let indent = &code[..code.len() - code.trim_start().len()];
+        out.push(format!(
+            "{}__expect({}, format!({}), {:?});",
+            indent,
+            i + 1,
+            args,
+            want
+        ));
+    }
+
+    if fns.is_empty() {
+        return Ok(());
+    }
+
+    out.push(String::new());
+    out.push("fn __expect(line: u32, got: String, want: &str) {".to_string());
+    out.push("    if got != want {".to_string());
+    out.push("        panic!(\"line {}: expected `{}`, got `{}`\", line, want, got);".to_string());
+    out.push("    }".to_string());
+    out.push("}".to_string());
+    out.push(String::new());
+    out.push("fn main() {".to_string());
+    for f in fns.iter() {
+        out.push(format!("    {}();", f));
+    }
+    out.push("}".to_string());
+
+    probe::run(&probe::name(fp, "expect"), &out.join("\n"))
+        .map(|_| ())
+        .map_err(|e| format!("{}: {}", fp, e))
+}
+
+fn print_args(code: &str) -> Option<&str> {
+    let code = code.trim();
+    let args = code
+        .strip_prefix("println!(")
+        .or_else(|| code.strip_prefix("print!("))?;
+    args.strip_suffix(");")
+}
+
+// Code and expected output of a line of code ending with an assertion.
+// Prose and doc comments are skipped, and so are markers in strings.
+fn assertion(line: &str) -> Option<(&str, &str)> {
+    if line.starts_with("//t") || line.trim_start().starts_with("///") {
+        return None;
+    }
+    let idx = comment_start(line)?;
+    let want = line[idx..].strip_prefix(MARKER)?;
+    Some((&line[..idx], want.trim_end()))
+}
+
+// Byte offset of the `//` comment of a line of code, outside of string
+// and char literals.
+pub fn comment_start(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut in_str = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if in_str => i += 1,
+            b'"' => in_str = !in_str,
+            b'/' if !in_str && bytes.get(i + 1) == Some(&b'/') => return Some(i),
+            // char literals, as opposed to lifetimes
+            b'\'' if !in_str => {
+                if bytes.get(i + 1) == Some(&b'\\') {
+                    i += 3;
+                    while i < bytes.len() && bytes[i] != b'\'' {
+                        i += 1;
+                    }
+                } else if bytes.get(i + 2) == Some(&b'\'') {
+                    i += 2;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_assertion() {
+        let line = r#"    println!("{}", add(2)); // -> 5"#;
+        assert_eq!(
+            assertion(line),
+            Some((r#"    println!("{}", add(2)); "#, "5"))
+        );
+    }
+
+    #[test]
+    fn marker_in_string() {
+        assert_eq!(assertion(r#"    println!("// -> 5");"#), None);
+        assert_eq!(
+            assertion(r#"    println!("a \" // -> b"); // -> c"#),
+            Some((r#"    println!("a \" // -> b"); "#, "c"))
+        );
+    }
+
+    #[test]
+    fn marker_after_char_literals() {
+        assert_eq!(
+            assertion(r#"    let q = '"'; // -> x"#).map(|a| a.1),
+            Some("x")
+        );
+        assert_eq!(
+            assertion(r"    let q = '\''; // -> x").map(|a| a.1),
+            Some("x")
+        );
+        assert_eq!(
+            assertion(r"    fn f<'a>(s: &'a str) {} // -> x").map(|a| a.1),
+            Some("x")
+        );
+    }
+
+    #[test]
+    fn prose_and_doc_comments() {
+        assert_eq!(
+            assertion("//t A comment such as `// -> 5` is checked."),
+            None
+        );
+        assert_eq!(assertion("    /// Returns 5, // -> 5"), None);
+    }
+
+    #[test]
+    fn other_comments() {
+        assert_eq!(assertion("    let x = 1; // x -> 1"), None);
+        assert_eq!(assertion("    let x = 1;"), None);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::Format;
+use crate::compile::{Doc, Section};
+use crate::links;
+
+// Consecutive code sections can be grouped as alternatives, shown as tabs
+// or side by side in HTML:
+//
+//     //t!tabs
+//     //t!tab Trait bound
+//     pub fn ex_use_notif<T: Notification>(item: T) { ... }
+//
+//     //t!tab `where` clause
+//     pub fn ex_use_notif_2<T>(item: T) where T: Notification { ... }
+//
+// Every code section is a block of the group, together with the callout
+// and the directives before it. Blocks are labelled by `//t!tab`, by
+// their callout, or else by the item they define. The group ends at the
+// next prose; a comparison has exactly two blocks. In markdown the blocks
+// follow one another, labelled.
+const TABS: &str = "//t!tabs";
+const COMPARE: &str = "//t!compare";
+const TAB: &str = "//t!tab ";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Tabs,
+    Compare,
+}
+
+struct Group {
+    kind: Kind,
+    line: usize,
+    blocks: Vec<Block>,
+}
+
+// Indices are of the sections of the doc.
+struct Block {
+    first: usize,
+    label: Option<String>,
+    callout: Option<usize>,
+    code: Option<usize>,
+}
+
+pub fn render(doc: &mut Doc, src: &str, format: Format) -> Result<(), String> {
+    let groups = groups(doc.sections(), src)?;
+
+    // from the end, so that indices stay valid
+    for g in groups.iter().rev() {
+        let (class, block_class) = match g.kind {
+            Kind::Tabs => ("code-tabs", "code-tab"),
+            Kind::Compare => ("code-compare", "code-column"),
+        };
+
+        let last = g.blocks.last().and_then(|b| b.code).unwrap();
+        if format == Format::Mdbook {
+            insert(doc, last + 1, "</div>".to_string());
+        }
+
+        for (n, b) in g.blocks.iter().enumerate().rev() {
+            let code = b.code.unwrap();
+            let label = label(doc, b, n);
+            // a callout is already a label
+            let shown = b.label.is_some() || b.callout.is_none();
+            match format {
+                Format::Markdown => {
+                    if shown {
+                        insert(doc, code, format!("**{}**", label));
+                    }
+                }
+                Format::Mdbook => {
+                    insert(doc, code + 1, "</div>".to_string());
+                    if shown {
+                        let p = format!("<p class=\"code-label\">{}</p>", inline_html(&label));
+                        insert(doc, code, p);
+                    }
+                    let div = format!(
+                        "<div class=\"{}\" data-label=\"{}\">",
+                        block_class,
+                        escape(&label.replace('`', ""))
+                    );
+                    insert(doc, b.first, div);
+                }
+            }
+        }
+
+        if format == Format::Mdbook {
+            let first = g.blocks[0].first;
+            insert(doc, first, format!("<div class=\"{}\">", class));
+        }
+    }
+    Ok(())
+}
+
+fn groups(sections: &[Section], src: &str) -> Result<Vec<Group>, String> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut current: Option<Group> = None;
+    for (idx, s) in sections.iter().enumerate() {
+        match s {
+            Section::Directive { start, lines } => {
+                for (i, l) in lines.iter().enumerate() {
+                    let kind = match l.trim_end() {
+                        TABS => Some(Kind::Tabs),
+                        COMPARE => Some(Kind::Compare),
+                        _ => None,
+                    };
+                    if let Some(kind) = kind {
+                        groups.extend(current.take());
+                        current = Some(Group {
+                            kind,
+                            line: start + i,
+                            blocks: Vec::new(),
+                        });
+                    } else if let Some(label) = l.strip_prefix(TAB) {
+                        let g = current.as_mut().ok_or(format!(
+                            "{}:{}: `{}` outside of a group",
+                            src,
+                            start + i,
+                            TAB.trim()
+                        ))?;
+                        g.blocks.push(Block {
+                            first: idx,
+                            label: Some(label.trim().to_string()).filter(|l| !l.is_empty()),
+                            callout: None,
+                            code: None,
+                        });
+                    }
+                }
+            }
+            Section::Callout { .. } => {
+                let g = match current.as_mut() {
+                    Some(g) => g,
+                    None => continue,
+                };
+                match g.blocks.last_mut() {
+                    Some(b) if b.code.is_none() && b.callout.is_none() => b.callout = Some(idx),
+                    _ => g.blocks.push(Block {
+                        first: idx,
+                        label: None,
+                        callout: Some(idx),
+                        code: None,
+                    }),
+                }
+            }
+            // blank lines between directives and code
+            Section::Code { lines, .. } if lines.is_empty() => {}
+            Section::Code { .. } => {
+                let g = match current.as_mut() {
+                    Some(g) => g,
+                    None => continue,
+                };
+                match g.blocks.last_mut() {
+                    Some(b) if b.code.is_none() => b.code = Some(idx),
+                    _ => g.blocks.push(Block {
+                        first: idx,
+                        label: None,
+                        callout: None,
+                        code: Some(idx),
+                    }),
+                }
+            }
+            _ => groups.extend(current.take()),
+        }
+    }
+    groups.extend(current);
+
+    for g in groups.iter() {
+        if g.blocks.iter().any(|b| b.code.is_none()) {
+            return Err(format!("{}:{}: group block without code", src, g.line));
+        }
+        let n = g.blocks.len();
+        match g.kind {
+            Kind::Tabs if n < 2 => {
+                return Err(format!("{}:{}: tabs need at least 2 blocks", src, g.line));
+            }
+            Kind::Compare if n != 2 => {
+                return Err(format!(
+                    "{}:{}: a comparison needs 2 blocks, found {}",
+                    src, g.line, n
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(groups)
+}
+
+fn label(doc: &Doc, b: &Block, n: usize) -> String {
+    if let Some(label) = &b.label {
+        return label.clone();
+    }
+    let sections = doc.sections();
+    if let Some(Section::Callout { lines, .. }) = b.callout.map(|i| &sections[i]) {
+        return lines.first().cloned().unwrap_or_default();
+    }
+    sections[b.code.unwrap()]
+        .lines()
+        .iter()
+        .find_map(|l| links::item_name(l))
+        .map_or(format!("Option {}", n + 1), |item| format!("`{}`", item))
+}
+
+fn insert(doc: &mut Doc, idx: usize, line: String) {
+    let start = doc.sections()[idx.min(doc.sections().len() - 1)].start();
+    doc.insert(
+        idx,
+        Section::Generated {
+            start,
+            lines: vec![line],
+        },
+    );
+}
+
+// Inline code as `<code>`, as HTML blocks aren't markdown.
+fn inline_html(s: &str) -> String {
+    if s.matches('`').count() % 2 == 1 {
+        return escape(s);
+    }
+    let mut out = String::new();
+    for (i, part) in escape(s).split('`').enumerate() {
+        if i > 0 {
+            out.push_str(if i % 2 == 1 { "<code>" } else { "</code>" });
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+
+    const SRC: &str = "//t Prose.
+
+//t!tabs
+//t!tab Trait bound
+fn a() {}
+
+// ✅ Cleaner.
+fn b() {}
+
+//t!tab
+fn c() {}
+
+//t Prose.
+";
+
+    fn render_with(src: &str, format: Format) -> Result<String, String> {
+        let mut doc = compile::compile("x.rs", src)?;
+        render(&mut doc, "x.rs", format)?;
+        Ok(doc.to_markdown())
+    }
+
+    #[test]
+    fn labels() {
+        let md = render_with(SRC, Format::Markdown).unwrap();
+        assert!(
+            md.contains("**Trait bound**\n\n```rust\nfn a() {}"),
+            "{}",
+            md
+        );
+        // labelled by the callout, not repeated
+        assert!(!md.contains("**Cleaner.**"), "{}", md);
+        assert!(md.contains("**`c`**\n\n```rust\nfn c() {}"), "{}", md);
+    }
+
+    #[test]
+    fn mdbook_nesting() {
+        let md = render_with(SRC, Format::Mdbook).unwrap();
+        let tags: Vec<&str> = md
+            .lines()
+            .filter(|l| l.starts_with("<div") || l.starts_with("</div") || l.starts_with("<p"))
+            .collect();
+        assert_eq!(
+            tags,
+            [
+                "<div class=\"code-tabs\">",
+                "<div class=\"code-tab\" data-label=\"Trait bound\">",
+                "<p class=\"code-label\">Trait bound</p>",
+                "</div>",
+                "<div class=\"code-tab\" data-label=\"Cleaner.\">",
+                "</div>",
+                "<div class=\"code-tab\" data-label=\"c\">",
+                "<p class=\"code-label\"><code>c</code></p>",
+                "</div>",
+                "</div>",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        for (src, err) in [
+            (
+                "//t!tab A\nfn a() {}\n",
+                "x.rs:1: `//t!tab` outside of a group",
+            ),
+            (
+                "//t!tabs\nfn a() {}\n",
+                "x.rs:1: tabs need at least 2 blocks",
+            ),
+            (
+                "//t!compare\nfn a() {}\n//t!tab\nfn b() {}\n//t!tab\nfn c() {}\n",
+                "x.rs:1: a comparison needs 2 blocks, found 3",
+            ),
+            (
+                "//t!tabs\nfn a() {}\n\n//t!tab B\n//t Prose.\n",
+                "x.rs:1: group block without code",
+            ),
+        ] {
+            assert_eq!(render_with(src, Format::Markdown).unwrap_err(), err);
+        }
+    }
+
+    #[test]
+    fn html_labels() {
+        assert_eq!(
+            inline_html("`Vec<T>` & co"),
+            "<code>Vec&lt;T&gt;</code> &amp; co"
+        );
+        assert_eq!(inline_html("odd ` tick"), "odd ` tick");
+        assert_eq!(escape("\"a\""), "&quot;a&quot;");
+    }
+}
@@ -0,0 +1,217 @@
+use crate::probe;
+
+// Layouts are requested with `//t!layout` directives. At the top level
+// the argument is a type, measured from a generated main:
+//
+//     //t!layout NotificationQueue<WhatsappMessage>
+//
+// Inside a function taking no arguments it is a binding in scope, e.g.
+// a closure, measured in place when the function is called:
+//
+//     let my_fn = move |n| println!("From thread: {:?}", list);
+//     //t!layout my_fn
+const DIRECTIVE: &str = "//t!layout ";
+const OUTPUT: &str = "#layout";
+
+pub struct Layout {
+    pub line: usize,
+    pub label: String,
+    pub size: usize,
+    pub align: usize,
+    // whether `Option` fits in the same size
+    pub niche: bool,
+}
+
+pub fn measure(fp: &str, src: &str) -> Result<Vec<Layout>, String> {
+    let probe_src = match probe_source(fp, src)? {
+        Some(probe_src) => probe_src,
+        None => return Ok(Vec::new()),
+    };
+    let stdout =
+        probe::run(&probe::name(fp, "layout"), &probe_src).map_err(|e| format!("{}: {}", fp, e))?;
+    parse(fp, &stdout)
+}
+
+// The chapter with every directive replaced by a measure, and a main
+// measuring the types and calling the functions measuring bindings.
+// None without directives.
+fn probe_source(fp: &str, src: &str) -> Result<Option<String>, String> {
+    let lines: Vec<&str> = src.lines().collect();
+    let callable = probe::callable_fns(&lines);
+
+    let mut out: Vec<String> = Vec::new();
+    let mut types: Vec<String> = Vec::new();
+    let mut fns: Vec<String> = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let arg = match line.trim_start().strip_prefix(DIRECTIVE) {
+            Some(arg) => arg.trim(),
+            None => {
+                out.push(line.to_string());
+                continue;
+            }
+        };
+
+        if !line.starts_with(char::is_whitespace) {
+            types.push(format!("    __layout::<{}>({}, {:?});", arg, i + 1, arg));
+            out.push(String::new());
+            continue;
+        }
+
+        let name = callable[i].clone().ok_or(format!(
+            "{}:{}: layout of a binding outside of a function without arguments",
+            fp,
+            i + 1
+        ))?;
+        if !fns.contains(&name) {
+            fns.push(name);
+        }
+
+        let indent = &line[..line.len() - line.trim_start().len()];
+        out.push(format!(
+            "{}__layout_val(&{}, {}, {:?});",
+            indent,
+            arg,
+            i + 1,
+            arg
+        ));
+    }
+
+    if types.is_empty() && fns.is_empty() {
+        return Ok(None);
+    }
+
+    out.push(String::new());
+    out.push("fn __layout<T>(line: u32, label: &str) {".to_string());
+    out.push("    let size = std::mem::size_of::<T>();".to_string());
+    out.push("    let align = std::mem::align_of::<T>();".to_string());
+    out.push("    let niche = std::mem::size_of::<Option<T>>() == size;".to_string());
+    out.push(format!(
+        "    println!(\"{}\\t{{}}\\t{{}}\\t{{}}\\t{{}}\\t{{}}\", line, size, align, niche, label);",
+        OUTPUT
+    ));
+    out.push("}".to_string());
+    out.push(String::new());
+    out.push("fn __layout_val<T>(_: &T, line: u32, label: &str) {".to_string());
+    out.push("    __layout::<T>(line, label);".to_string());
+    out.push("}".to_string());
+    out.push(String::new());
+    out.push("fn main() {".to_string());
+    out.extend(types);
+    for f in fns.iter() {
+        out.push(format!("    {}();", f));
+    }
+    out.push("}".to_string());
+    Ok(Some(out.join("\n")))
+}
+
+// Layouts printed by the probe, in source order.
+fn parse(fp: &str, stdout: &str) -> Result<Vec<Layout>, String> {
+    let mut layouts: Vec<Layout> = Vec::new();
+    for l in stdout.lines() {
+        let fields: Vec<&str> = match l.strip_prefix(OUTPUT) {
+            Some(rest) => rest.trim_start_matches('\t').splitn(5, '\t').collect(),
+            None => continue,
+        };
+        if fields.len() != 5 {
+            return Err(format!("{}: malformed layout output `{}`", fp, l));
+        }
+
+        let num = |s: &str| s.parse::<usize>().map_err(|e| e.to_string());
+        layouts.push(Layout {
+            line: num(fields[0])?,
+            size: num(fields[1])?,
+            align: num(fields[2])?,
+            niche: fields[3] == "true",
+            label: fields[4].to_string(),
+        });
+    }
+
+    // functions may run in any order, keep the source one
+    layouts.sort_by_key(|l| l.line);
+    layouts.dedup_by_key(|l| l.line);
+    Ok(layouts)
+}
+
+pub fn table(layouts: &[&Layout]) -> Vec<String> {
+    let mut lines = vec![
+        "| Item | Size | Align | Niche for `Option` |".to_string(),
+        "|:---|:---:|:---:|:---:|".to_string(),
+    ];
+
+    for l in layouts {
+        lines.push(format!(
+            "| `{}` | {} | {} | {} |",
+            l.label,
+            l.size,
+            l.align,
+            if l.niche { "✅" } else { "🚫" }
+        ));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "struct Pair(u8, u32);
+//t!layout Pair
+
+fn ex() {
+    let b = Box::new(1);
+    //t!layout b
+}";
+
+    #[test]
+    fn directives() {
+        let probe_src = probe_source("x.rs", SRC).unwrap().unwrap();
+        let lines: Vec<&str> = probe_src.lines().collect();
+        assert_eq!(lines[1], "");
+        assert_eq!(lines[5], "    __layout_val(&b, 6, \"b\");");
+        let main = lines.iter().position(|l| *l == "fn main() {").unwrap();
+        assert_eq!(
+            lines[main..],
+            [
+                "fn main() {",
+                "    __layout::<Pair>(2, \"Pair\");",
+                "    ex();",
+                "}"
+            ]
+        );
+
+        assert_eq!(probe_source("x.rs", "fn a() {}").unwrap(), None);
+        assert_eq!(
+            probe_source("x.rs", "fn a(n: u8) {\n    //t!layout n\n}").unwrap_err(),
+            "x.rs:2: layout of a binding outside of a function without arguments"
+        );
+    }
+
+    #[test]
+    fn output() {
+        let stdout = "noise\n#layout\t6\t8\t8\ttrue\tb\n#layout\t2\t8\t4\tfalse\tPair<u8, u32>\n#layout\t6\t8\t8\ttrue\tb\n";
+        let layouts = parse("x.rs", stdout).unwrap();
+        let found: Vec<(usize, &str, usize, usize, bool)> = layouts
+            .iter()
+            .map(|l| (l.line, l.label.as_str(), l.size, l.align, l.niche))
+            .collect();
+        assert_eq!(
+            found,
+            [(2, "Pair<u8, u32>", 8, 4, false), (6, "b", 8, 8, true)]
+        );
+
+        assert_eq!(
+            parse("x.rs", "#layout\t2\t8").err().unwrap(),
+            "x.rs: malformed layout output `#layout\t2\t8`"
+        );
+    }
+
+    #[test]
+    fn measures() {
+        let layouts = measure("x.rs", SRC).unwrap();
+        assert_eq!(layouts.len(), 2);
+        assert_eq!((layouts[0].size, layouts[0].align), (8, 4));
+        assert!(layouts[1].niche);
+    }
+}
@@ -1,19 +1,493 @@
+pub mod annotation;
+pub mod asm;
+pub mod auto_traits;
+pub mod callout;
+#[allow(dead_code, unused, clippy::all)]
 pub mod chapters;
+pub mod classify;
+pub mod code;
 pub mod compile;
+pub mod config;
+pub mod demangle;
+pub mod diff;
+pub mod discover;
+pub mod expect;
+pub mod group;
+pub mod json;
+pub mod layout;
+pub mod linkcheck;
+pub mod links;
+pub mod lint;
+pub mod markdown;
+pub mod mdbook;
+pub mod meta;
+pub mod preprocessor;
+pub mod probe;
+pub mod readme;
+pub mod reflow;
+pub mod region;
+pub mod rustfmt;
+pub mod scaffold;
+pub mod spell;
+pub mod steps;
+pub mod wordlist;
 
+use preprocessor::Preprocessor;
 use std::fs;
 
+// Chapters written in markdown, included as they are. Chapters written as
+// `.rs` files are discovered under `src/chapters/` and compiled.
+pub const MARKDOWN_CHAPTERS: &[(&str, &str)] = &[(
+    "interior_mut_shared_ownership_thread_safety",
+    "./interior_mut_shared_ownership_thread_safety/README.md",
+)];
+
+// Standalone files listed in the readme, as (title, path).
+pub const MATERIAL: &[(&str, &str)] = &[(
+    "Interior mutability, shared ownership & thread safety (PDF)",
+    "./interior_mut_shared_ownership_thread_safety/interior_mut_shared_ownership_thread_safety.pdf",
+)];
+
+pub const README: &str = "./README.md";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Markdown,
+    Mdbook,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Result<Format, String> {
+        match s {
+            "markdown" => Ok(Format::Markdown),
+            "mdbook" => Ok(Format::Mdbook),
+            _ => Err(format!("unknown format `{}`", s)),
+        }
+    }
+
+    // Name of the format, also used as its configuration table.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Markdown => "markdown",
+            Format::Mdbook => "mdbook",
+        }
+    }
+
+    pub fn default_dir(&self) -> &'static str {
+        match self {
+            Format::Markdown => "./docs",
+            Format::Mdbook => "./book",
+        }
+    }
+}
+
 pub fn compile_docs() -> Result<(), String> {
-    match fs::create_dir_all("./docs") {
-        Err(e) => return Err(format!("creating dir: {}", e)),
-        Ok(_) => (),
+    build(Format::Markdown, Format::Markdown.default_dir())
+}
+
+pub fn build(format: Format, dir: &str) -> Result<(), String> {
+    build_with(format, dir, &Filter::default(), Vec::new())
+}
+
+// A compiled chapter, ready to be written in any format.
+pub struct Chapter {
+    // file name of the chapter, without extension
+    pub name: String,
+    pub src: String,
+    pub title: String,
+    pub content: String,
+    // source line of every line of the content
+    pub lines: Vec<usize>,
+    // output file, relative to the book sources for mdBook
+    pub file: String,
+    pub meta: meta::Meta,
+}
+
+// A chapter before rendering: compiled from a `.rs` file, or the body of
+// a markdown one.
+enum Source {
+    Rust(compile::Doc),
+    // body and number of lines of the front matter before it
+    Markdown(String, usize),
+}
+
+// Chapters to build: when a list is not empty, a chapter must match
+// one of its values.
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub tags: Vec<String>,
+    pub statuses: Vec<String>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.statuses.is_empty()
+    }
+
+    pub fn matches(&self, meta: &meta::Meta) -> bool {
+        let tag = self.tags.is_empty() || meta.tags.iter().any(|t| self.tags.contains(t));
+        let status = self.statuses.is_empty()
+            || meta
+                .status
+                .as_ref()
+                .is_some_and(|s| self.statuses.contains(s));
+        tag && status
+    }
+}
+
+// Builds the docs running the given preprocessors after the configured ones.
+pub fn build_with(
+    format: Format,
+    dir: &str,
+    filter: &Filter,
+    extra: Vec<Box<dyn Preprocessor>>,
+) -> Result<(), String> {
+    let config = config::Config::load(config::CONFIG_FILE)?;
+    for w in discover::check(&config, MARKDOWN_CHAPTERS)? {
+        eprintln!("warning: {}", w);
+    }
+    if !asm::SUPPORTED {
+        eprintln!("warning: assembly is only shown on x86-64 ELF targets, skipping `//t!asm`");
     }
 
-    let doc = compile::compile("./src/chapters/closures.rs")?;
-    doc.write_to_file("./docs/closures.md")?;
+    auto_traits::verify()?;
+    auto_traits::write_tables("./interior_mut_shared_ownership_thread_safety/README.md")?;
+
+    let chapters = compile_book(&config, format, dir, filter, extra)?;
+    let toc_depth = config
+        .get_int(&format!("{}.toc_depth", format.name()))?
+        .unwrap_or(2) as usize;
+    let index = config
+        .get_bool(&format!("{}.index", format.name()))?
+        .unwrap_or(false);
+
+    match format {
+        Format::Markdown => {
+            if let Err(e) = fs::create_dir_all(dir) {
+                return Err(format!("creating dir: {}", e));
+            }
 
-    let doc = compile::compile("./src/chapters/traits.rs")?;
-    doc.write_to_file("./docs/traits.md")?;
+            // markdown chapters are already in place
+            for c in chapters.iter().filter(|c| c.src.ends_with(".rs")) {
+                let mut content = c.meta.front_matter().join("\n");
+                if !content.is_empty() {
+                    content.push_str("\n\n");
+                }
+                content.push_str(&c.content);
+                fs::write(&c.file, content).map_err(|e| format!("writing {}: {}", c.file, e))?;
+            }
+
+            if index {
+                let fp = format!("{}/index.md", dir);
+                let links: Vec<String> = chapters
+                    .iter()
+                    .map(|c| markdown::relative(&fp, &c.file))
+                    .collect();
+                let lines = markdown::index(mdbook::TITLE, &chapters, &links, toc_depth);
+                fs::write(&fp, lines.join("\n")).map_err(|e| format!("writing {}: {}", fp, e))?;
+            }
+
+            // the readme lists the whole book
+            if !filter.is_empty() {
+                return Ok(());
+            }
+            let links: Vec<String> = chapters
+                .iter()
+                .map(|c| markdown::relative(README, &c.file))
+                .collect();
+            readme::write(README, &chapters, &links, MATERIAL)
+        }
+        Format::Mdbook => mdbook::write_book(dir, &chapters, index),
+    }
+}
+
+// Compiles every chapter in reading order and renders it as markdown,
+// with the chapter preprocessors and the links between chapters resolved
+// for the output format.
+pub fn compile_book(
+    config: &config::Config,
+    format: Format,
+    dir: &str,
+    filter: &Filter,
+    extra: Vec<Box<dyn Preprocessor>>,
+) -> Result<Vec<Chapter>, String> {
+    let mut preprocessors = preprocessors(config, format)?;
+    preprocessors.extend(extra);
+
+    // every chapter is compiled, even the filtered out ones, so that
+    // links to them can be checked
+    let mut compiled: Vec<(String, String, Source, meta::Meta)> = Vec::new();
+    for (name, src) in discover::chapters(config, MARKDOWN_CHAPTERS)? {
+        if src.ends_with(".rs") {
+            let mut doc = compile_chapter(&src)?;
+            preprocessor::run_all(&mut doc, &preprocessors)
+                .map_err(|e| format!("{}: {}", src, e))?;
+            let meta = doc.meta().clone();
+            compiled.push((name, src, Source::Rust(doc), meta));
+        } else {
+            let content =
+                fs::read_to_string(&src).map_err(|e| format!("reading {}: {}", src, e))?;
+            let (meta, body) =
+                meta::parse_front_matter(&content).map_err(|e| format!("{}: {}", src, e))?;
+            let offset = content[..content.len() - body.len()].lines().count();
+            compiled.push((name, src, Source::Markdown(body.to_string(), offset), meta));
+        }
+    }
+
+    // prerequisites name chapters as links do, by slug or file name
+    for (_, src, _, meta) in compiled.iter() {
+        for p in meta.prerequisites.iter() {
+            let found = compiled
+                .iter()
+                .any(|(name, _, _, m)| m.slug.as_ref().unwrap_or(name) == p);
+            if !found {
+                return Err(format!("{}: unknown prerequisite chapter `{}`", src, p));
+            }
+        }
+    }
 
+    // output file of every chapter, links between them are relative
+    let file = |name: &str, src: &str, meta: &meta::Meta| -> String {
+        let name = meta.slug.clone().unwrap_or(name.to_string());
+        match format {
+            Format::Markdown if src.ends_with(".rs") => format!("{}/{}.md", dir, name),
+            Format::Markdown => src.to_string(),
+            Format::Mdbook => format!("{}.md", name),
+        }
+    };
+
+    let mut targets: Vec<links::Target> = Vec::new();
+    for (name, src, source, meta) in compiled.iter() {
+        let file = Some(file(name, src, meta)).filter(|_| filter.matches(meta));
+        let target = match source {
+            Source::Rust(doc) => links::Target::from_doc(name, &title(name, doc), file, doc),
+            Source::Markdown(content, _) => {
+                let title = meta
+                    .title
+                    .clone()
+                    .or(markdown::title(content))
+                    .unwrap_or(name.to_string());
+                links::Target::from_markdown(name, &title, file, content)
+            }
+        };
+        targets.push(target);
+    }
+
+    let mut chapters: Vec<Chapter> = Vec::new();
+    for ((name, src, source, meta), target) in compiled.into_iter().zip(targets.iter()) {
+        let from = match &target.file {
+            Some(file) => file,
+            None => continue,
+        };
+
+        let (content, lines) = match source {
+            Source::Rust(mut doc) => {
+                render_chapter(&mut doc, &src, &name, from, &targets, format)?;
+                doc.to_markdown_with_lines()
+            }
+            Source::Markdown(content, offset) => {
+                let lines = (1..=content.lines().count()).map(|l| l + offset).collect();
+                (content, lines)
+            }
+        };
+
+        chapters.push(Chapter {
+            name: meta.slug.clone().unwrap_or(name),
+            src,
+            title: target.title.clone(),
+            content,
+            lines,
+            file: from.clone(),
+            meta,
+        });
+    }
+
+    Ok(chapters)
+}
+
+// Preprocessors of a format: the configured ones, then the table of
+// contents.
+pub fn preprocessors(
+    config: &config::Config,
+    format: Format,
+) -> Result<Vec<Box<dyn Preprocessor>>, String> {
+    let mut preprocessors = config
+        .get_strings(&format!("{}.preprocessors", format.name()))?
+        .iter()
+        .map(|spec| preprocessor::from_spec(spec))
+        .collect::<Result<Vec<Box<dyn Preprocessor>>, String>>()?;
+    let toc_depth = config
+        .get_int(&format!("{}.toc_depth", format.name()))?
+        .unwrap_or(2) as usize;
+    preprocessors.push(Box::new(preprocessor::Toc {
+        depth: toc_depth,
+        auto: config
+            .get_bool(&format!("{}.toc", format.name()))?
+            .unwrap_or(false),
+    }));
+    Ok(preprocessors)
+}
+
+// Title of a compiled chapter: from its metadata, or its first heading.
+pub fn title(name: &str, doc: &compile::Doc) -> String {
+    doc.meta()
+        .title
+        .clone()
+        .or(markdown::title(&doc.to_markdown()))
+        .unwrap_or(name.to_string())
+}
+
+// Renders a compiled chapter for the output format, its links resolved
+// relative to `from`, the output file of the chapter `name`.
+pub fn render_chapter(
+    doc: &mut compile::Doc,
+    src: &str,
+    name: &str,
+    from: &str,
+    targets: &[links::Target],
+    format: Format,
+) -> Result<(), String> {
+    links::resolve(doc, src, name, from, targets)?;
+    group::render(doc, src, format)?;
+    code::render(doc, format);
+    callout::render(doc, format);
+    annotation::render(doc, format);
     Ok(())
 }
+
+// Checks the links of the docs built in `dir`, compiling the book again
+// to trace broken links back to the chapter sources.
+pub fn check_links(format: Format, dir: &str) -> Result<linkcheck::Report, String> {
+    let config = config::Config::load(config::CONFIG_FILE)?;
+    let chapters = compile_book(&config, format, dir, &Filter::default(), Vec::new())?;
+    let extra: &[&str] = match format {
+        Format::Markdown => &[README],
+        Format::Mdbook => &[],
+    };
+    linkcheck::check(format, dir, extra, &chapters)
+}
+
+// The given chapter sources, or every chapter written as a `.rs` file when
+// none is given.
+fn chapter_files(config: &config::Config, files: &[String]) -> Result<Vec<String>, String> {
+    match files {
+        [] => Ok(discover::chapters(config, MARKDOWN_CHAPTERS)?
+            .into_iter()
+            .map(|(_, src)| src)
+            .filter(|src| src.ends_with(".rs"))
+            .collect()),
+        files => Ok(files.to_vec()),
+    }
+}
+
+// Lints the prose of the given chapter sources, or of every chapter when
+// none is given.
+pub fn lint(files: &[String]) -> Result<Vec<lint::Diagnostic>, String> {
+    let config = config::Config::load(config::CONFIG_FILE)?;
+    let opts = lint::Options::from_config(&config)?;
+    let files = chapter_files(&config, files)?;
+
+    let mut out = Vec::new();
+    for fp in files.iter() {
+        let content = fs::read_to_string(fp).map_err(|e| format!("reading {}: {}", fp, e))?;
+        out.extend(lint::lint(fp, &content, &opts)?);
+    }
+    Ok(out)
+}
+
+// Spell checks the given chapter sources, or every chapter when none is
+// given. With `confirm`, misspellings having a single suggestion a single
+// edit away are fixed in place when it accepts them, and the others are
+// returned.
+pub fn spell(
+    files: &[String],
+    mut confirm: Option<&mut dyn FnMut(&spell::Misspelling) -> bool>,
+) -> Result<Vec<spell::Misspelling>, String> {
+    let config = config::Config::load(config::CONFIG_FILE)?;
+    let dict = spell::Dictionary::load(&config)?;
+    let files = chapter_files(&config, files)?;
+
+    let mut out = Vec::new();
+    for fp in files.iter() {
+        let content = fs::read_to_string(fp).map_err(|e| format!("reading {}: {}", fp, e))?;
+        let found = spell::check(fp, &content, &dict)?;
+        let confirm = match confirm.as_mut() {
+            Some(confirm) if !found.is_empty() => confirm,
+            _ => {
+                out.extend(found);
+                continue;
+            }
+        };
+        let (fixed, left) = spell::fix(&content, found, *confirm);
+        if let Some(fixed) = fixed {
+            fs::write(fp, fixed).map_err(|e| format!("writing {}: {}", fp, e))?;
+        }
+        out.extend(left);
+    }
+    Ok(out)
+}
+
+// Formats the prose of the given chapter sources, or of every chapter
+// when none is given, returning the files that changed. With `check`,
+// files are left untouched.
+pub fn fmt(files: &[String], check: bool) -> Result<Vec<String>, String> {
+    let config = config::Config::load(config::CONFIG_FILE)?;
+    let opts = reflow::Options::from_config(&config)?;
+    let files = chapter_files(&config, files)?;
+
+    let mut changed = Vec::new();
+    for fp in files {
+        let src = fs::read_to_string(&fp).map_err(|e| format!("reading {}: {}", fp, e))?;
+        let formatted = reflow::format(&src, &opts);
+        if formatted == src {
+            continue;
+        }
+        if !check {
+            fs::write(&fp, formatted).map_err(|e| format!("writing {}: {}", fp, e))?;
+        }
+        changed.push(fp);
+    }
+    Ok(changed)
+}
+
+// Compiles a chapter with its checks run and the tables generated by its
+// directives in place, for every output format.
+pub fn compile_chapter(fp: &str) -> Result<compile::Doc, String> {
+    let src = fs::read_to_string(fp).map_err(|e| format!("reading {}: {}", fp, e))?;
+    expect::verify(fp, &src)?;
+    callout::verify(fp, &src)?;
+    let classes = classify::classify(fp, &src)?;
+    let layouts = layout::measure(fp, &src)?;
+    let asms = asm::extract(fp, &src)?;
+    let diffs = diff::extract(fp, &src)?;
+
+    let mut doc = compile::compile(fp, &src)?;
+    insert_tables(&mut doc, &classes, |c| c.line, classify::table);
+    insert_tables(&mut doc, &layouts, |l| l.line, layout::table);
+    insert_tables(&mut doc, &asms, |a| a.line, asm::render);
+    insert_tables(&mut doc, &diffs, |d| d.line, diff::render);
+
+    Ok(doc)
+}
+
+// Inserts the rendering of the items generated from the directives of a
+// section, right after the section itself.
+fn insert_tables<T>(
+    doc: &mut compile::Doc,
+    items: &[T],
+    line: fn(&T) -> usize,
+    render: fn(&[&T]) -> Vec<String>,
+) {
+    let mut i = 0;
+    while i < items.len() {
+        let start = doc.section_start(line(&items[i]));
+        let group: Vec<&T> = items[i..]
+            .iter()
+            .take_while(|item| doc.section_start(line(item)) == start)
+            .collect();
+        i += group.len();
+
+        let last = line(group.last().unwrap());
+        doc.insert_after(last, render(&group));
+    }
+}
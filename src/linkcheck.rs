@@ -0,0 +1,361 @@
+use crate::{Chapter, Format, markdown, mdbook};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// Offline check of the links of the generated files: relative links must
+// point to an existing file, and to an existing heading (or `id`) when
+// they have an anchor. External links are collected, never fetched.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub broken: Vec<Broken>,
+    // (file, line, url)
+    pub external: Vec<(String, usize, String)>,
+}
+
+#[derive(Debug)]
+pub struct Broken {
+    pub file: String,
+    pub line: usize,
+    pub target: String,
+    pub reason: String,
+    // chapter source and line the link comes from, when known
+    pub source: Option<(String, usize)>,
+}
+
+// Checks every markdown and HTML file under `dir`, plus the given files
+// (e.g. the readme). The chapters, as compiled for the build, map lines
+// of the generated files back to their sources.
+pub fn check(
+    format: Format,
+    dir: &str,
+    extra: &[&str],
+    chapters: &[Chapter],
+) -> Result<Report, String> {
+    let mut files = Vec::new();
+    walk(Path::new(dir), &mut files)?;
+    files.extend(extra.iter().map(PathBuf::from));
+    if format == Format::Markdown {
+        files.extend(
+            chapters
+                .iter()
+                .filter(|c| !c.src.ends_with(".rs"))
+                .map(|c| PathBuf::from(&c.file)),
+        );
+    }
+
+    let mut report = Report::default();
+    let mut anchors: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    for file in files.iter() {
+        let content =
+            fs::read_to_string(file).map_err(|e| format!("reading {}: {}", file.display(), e))?;
+
+        for (line, target) in links(file, &content) {
+            if target.contains("://") || target.starts_with("mailto:") {
+                report
+                    .external
+                    .push((file.display().to_string(), line, target));
+                continue;
+            }
+
+            let reason = match check_link(file, dir, &target, &mut anchors)? {
+                Some(reason) => reason,
+                None => continue,
+            };
+            report.broken.push(Broken {
+                file: file.display().to_string(),
+                line,
+                target,
+                reason,
+                source: source(format, dir, file, line, chapters),
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+// Reason the link is broken, if it is.
+fn check_link(
+    file: &Path,
+    dir: &str,
+    target: &str,
+    anchors: &mut HashMap<PathBuf, Vec<String>>,
+) -> Result<Option<String>, String> {
+    let (path, anchor) = match target.split_once('#') {
+        Some((path, anchor)) => (path, Some(anchor)),
+        None => (target, None),
+    };
+
+    let path = if path.is_empty() {
+        file.to_path_buf()
+    } else if let Some(abs) = path.strip_prefix('/') {
+        Path::new(dir).join(abs)
+    } else {
+        file.parent().unwrap_or(Path::new("")).join(path)
+    };
+    let path = match fs::canonicalize(&path) {
+        Ok(path) => path,
+        Err(_) => return Ok(Some(format!("no file {}", path.display()))),
+    };
+
+    let anchor = match anchor {
+        Some(anchor) if !anchor.is_empty() && path.is_file() => anchor,
+        _ => return Ok(None),
+    };
+    if !anchors.contains_key(&path) {
+        let content =
+            fs::read_to_string(&path).map_err(|e| format!("reading {}: {}", path.display(), e))?;
+        anchors.insert(path.clone(), file_anchors(&path, &content));
+    }
+
+    if anchors[&path].iter().any(|a| a == anchor) {
+        Ok(None)
+    } else {
+        Ok(Some(format!(
+            "no anchor `#{}` in {}",
+            anchor,
+            path.display()
+        )))
+    }
+}
+
+// Targets of the links and images of a file, with their line.
+fn links(file: &Path, content: &str) -> Vec<(usize, String)> {
+    let html = is_html(file);
+    let mut out = Vec::new();
+    let mut fenced = false;
+    for (i, line) in content.lines().enumerate() {
+        if !html && line.trim_start().starts_with("```") {
+            fenced = !fenced;
+            continue;
+        }
+        if fenced {
+            continue;
+        }
+
+        let line = if html {
+            line.to_string()
+        } else {
+            strip_code_spans(line)
+        };
+        for target in attributes(&line, &["href", "src"]) {
+            out.push((i + 1, target));
+        }
+        if !html {
+            for target in markdown_links(&line) {
+                out.push((i + 1, target));
+            }
+        }
+    }
+
+    out.retain(|(_, t)| !t.is_empty() && t != "#" && !t.starts_with("javascript:"));
+    out
+}
+
+// Targets of `[text](target)` and `![alt](target)`, without titles.
+fn markdown_links(line: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut rest = line;
+    while let Some(i) = rest.find("](") {
+        rest = &rest[i + 2..];
+        let end = match rest.find(')') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let target = rest[..end].trim();
+        let target = target.split_once(' ').map_or(target, |(t, _)| t);
+        out.push(target.trim_matches(['<', '>']).to_string());
+        rest = &rest[end..];
+    }
+    out
+}
+
+// Values of the given HTML attributes, e.g. `href="..."`.
+fn attributes(line: &str, names: &[&str]) -> Vec<String> {
+    let mut out = Vec::new();
+    for name in names {
+        let pattern = format!(" {}=\"", name);
+        let mut rest = line;
+        while let Some(i) = rest.find(&pattern) {
+            rest = &rest[i + pattern.len()..];
+            let end = rest.find('"').unwrap_or(rest.len());
+            out.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+    }
+    out
+}
+
+// Anchors a file defines: its headings for markdown, and the `id`s.
+fn file_anchors(path: &Path, content: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    if !is_html(path) {
+        let lines: Vec<&str> = content.lines().collect();
+        out.extend(markdown::anchors(&markdown::headings(&lines)));
+    }
+    for line in content.lines() {
+        out.extend(attributes(line, &["id", "name"]));
+    }
+    out
+}
+
+fn strip_code_spans(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    for (i, part) in line.split('`').enumerate() {
+        if i % 2 == 0 {
+            out.push_str(part);
+        }
+    }
+    out
+}
+
+fn is_html(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "html")
+}
+
+// Source line of a line of a generated file, through the chapter that
+// produced it.
+fn source(
+    format: Format,
+    dir: &str,
+    file: &Path,
+    line: usize,
+    chapters: &[Chapter],
+) -> Option<(String, usize)> {
+    for c in chapters {
+        let first = match format {
+            Format::Markdown if same_file(&c.file, file) => {
+                if !c.src.ends_with(".rs") {
+                    return Some((c.src.clone(), line));
+                }
+                // the front matter precedes the content
+                let front_matter = c.meta.front_matter().len();
+                let skip = front_matter + if front_matter > 0 { 1 } else { 0 };
+                (line - 1).checked_sub(skip)?
+            }
+            Format::Markdown => continue,
+            Format::Mdbook => {
+                let page = mdbook::pages(c)
+                    .into_iter()
+                    .find(|(page, _)| same_file(&format!("{}/src/{}", dir, page), file));
+                match page {
+                    Some((_, first)) => first + line - 1,
+                    None => continue,
+                }
+            }
+        };
+        return c.lines.get(first).map(|l| (c.src.clone(), *l));
+    }
+    None
+}
+
+fn same_file(a: &str, b: &Path) -> bool {
+    let parts = |p: &Path| -> Vec<String> {
+        p.components()
+            .filter(|c| *c != Component::CurDir)
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect()
+    };
+    parts(Path::new(a)) == parts(b)
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("reading {}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .map(|e| e.map(|e| e.path()).map_err(|e| e.to_string()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else if path.extension().is_some_and(|e| e == "md" || e == "html") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_targets() {
+        let content = "\
+See [a](a.md#x) and ![img](<img.png> \"title\").
+`[not](code.md)` [b](b.md) <a href=\"c.html\">c</a>
+```
+[fenced](f.md)
+```
+[empty]() [top](#) [js](javascript:void(0))
+";
+        assert_eq!(
+            links(Path::new("x.md"), content),
+            [
+                (1, "a.md#x".to_string()),
+                (1, "img.png".to_string()),
+                (2, "c.html".to_string()),
+                (2, "b.md".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn html_targets() {
+        let content = "<p>`<a href=\"a.html\">`</p>\n<img src=\"i.png\"> [md](m.md)\n";
+        assert_eq!(
+            links(Path::new("x.html"), content),
+            [(1, "a.html".to_string()), (2, "i.png".to_string())]
+        );
+    }
+
+    #[test]
+    fn anchors_of_a_file() {
+        let content = "# Traits\n\n## Traits\n\n<span id=\"custom\"></span>\n";
+        assert_eq!(
+            file_anchors(Path::new("x.md"), content),
+            ["traits", "traits-1", "custom"]
+        );
+        assert_eq!(file_anchors(Path::new("x.html"), content), ["custom"]);
+    }
+
+    #[test]
+    fn same_files() {
+        assert!(same_file("./docs/a.md", Path::new("docs/a.md")));
+        assert!(!same_file("docs/a.md", Path::new("docs/b.md")));
+    }
+
+    #[test]
+    fn broken_links() {
+        let dir = std::env::temp_dir().join(format!("handbook-links-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(
+            dir.join("a.md"),
+            "# A\n\n[ok](sub/b.md#b) [self](#a) [gone](c.md)\n[bad](sub/b.md#nope) [web](https://x.org)\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("sub/b.md"),
+            "# B\n\n[up](../a.md) [root](/a.md#a)\n",
+        )
+        .unwrap();
+
+        let dir_s = dir.to_string_lossy().to_string();
+        let report = check(Format::Markdown, &dir_s, &[], &[]).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let broken: Vec<(usize, &str)> = report
+            .broken
+            .iter()
+            .map(|b| (b.line, b.target.as_str()))
+            .collect();
+        assert_eq!(broken, [(3, "c.md"), (4, "sub/b.md#nope")]);
+        assert!(report.broken[1].reason.starts_with("no anchor `#nope` in "));
+        assert_eq!(report.external.len(), 1);
+        assert_eq!(report.external[0].1, 4);
+        assert_eq!(report.external[0].2, "https://x.org");
+    }
+}
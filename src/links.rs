@@ -0,0 +1,337 @@
+use crate::compile::{Doc, Section};
+use crate::markdown::{self, Heading};
+
+// Links to other chapters written in prose, resolved at build time to a
+// relative link for the output format:
+//
+//     [[traits]]                       the chapter, with its title
+//     [[traits#traits-as-bounds]]      a heading, with its text
+//     [[traits::Notification]]         an item, linked to its section
+//
+// The text can be set explicitly: [[traits#traits-as-bounds|bounds]].
+const OPEN: &str = "[[";
+const CLOSE: &str = "]]";
+
+// A chapter links can point to. Chapters not part of the build (e.g.
+// filtered out) have no file: links to them are rendered as plain text.
+pub struct Target {
+    pub name: String,
+    pub title: String,
+    pub file: Option<String>,
+    // (anchor, text) of every heading
+    headings: Vec<(String, String)>,
+    // (name, anchor of the enclosing heading) of every item
+    items: Vec<(String, Option<String>)>,
+}
+
+impl Target {
+    pub fn from_doc(name: &str, title: &str, file: Option<String>, doc: &Doc) -> Target {
+        let mut headings: Vec<Heading> = Vec::new();
+        let mut items: Vec<(String, usize)> = Vec::new();
+        for s in doc.sections() {
+            match s {
+                Section::Comment { lines, .. } => {
+                    let refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+                    headings.extend(markdown::headings(&refs));
+                }
+                Section::Code { lines, .. } => {
+                    for l in lines {
+                        if let Some(item) = item_name(l) {
+                            items.push((item.to_string(), headings.len()));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let anchors = markdown::anchors(&headings);
+        Target {
+            name: name.to_string(),
+            title: title.to_string(),
+            file,
+            items: items
+                .into_iter()
+                .map(|(item, n)| (item, n.checked_sub(1).map(|i| anchors[i].clone())))
+                .collect(),
+            headings: anchors
+                .into_iter()
+                .zip(headings)
+                .map(|(a, h)| (a, h.text))
+                .collect(),
+        }
+    }
+
+    pub fn from_markdown(name: &str, title: &str, file: Option<String>, content: &str) -> Target {
+        let lines: Vec<&str> = content.lines().collect();
+        let headings = markdown::headings(&lines);
+        let anchors = markdown::anchors(&headings);
+        Target {
+            name: name.to_string(),
+            title: title.to_string(),
+            file,
+            headings: anchors
+                .into_iter()
+                .zip(headings)
+                .map(|(a, h)| (a, h.text))
+                .collect(),
+            items: Vec::new(),
+        }
+    }
+}
+
+// Replaces the links in the prose of the chapter `name`, whose output file
+// is `from`. Unknown chapters, headings and items are errors pointing at
+// the line of the chapter source `src`.
+pub fn resolve(
+    doc: &mut Doc,
+    src: &str,
+    name: &str,
+    from: &str,
+    targets: &[Target],
+) -> Result<(), String> {
+    for s in doc.sections_mut() {
+        let (start, lines) = match s {
+            Section::Comment { start, lines }
+            | Section::Callout { start, lines, .. }
+            | Section::Annotations { start, lines } => (*start, lines),
+            _ => continue,
+        };
+
+        let mut fenced = false;
+        for (i, l) in lines.iter_mut().enumerate() {
+            if l.trim_start().starts_with("```") {
+                fenced = !fenced;
+            }
+            if fenced || !l.contains(OPEN) {
+                continue;
+            }
+            *l = resolve_line(l, name, from, targets)
+                .map_err(|e| format!("{}:{}: {}", src, start + i, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn resolve_line(line: &str, name: &str, from: &str, targets: &[Target]) -> Result<String, String> {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(i) = rest.find(OPEN) {
+        // links in inline code are left as they are
+        let in_code = (out.matches('`').count() + rest[..i].matches('`').count()) % 2 == 1;
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let end = match rest.find(CLOSE) {
+            Some(end) if !in_code => end,
+            _ => {
+                out.push_str(OPEN);
+                rest = &rest[OPEN.len()..];
+                continue;
+            }
+        };
+
+        out.push_str(&render(&rest[OPEN.len()..end], name, from, targets)?);
+        rest = &rest[end + CLOSE.len()..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn render(link: &str, name: &str, from: &str, targets: &[Target]) -> Result<String, String> {
+    let (link, text) = match link.split_once('|') {
+        Some((link, text)) => (link.trim(), Some(text.trim())),
+        None => (link.trim(), None),
+    };
+
+    let (chapter, anchor, default_text) = if let Some((chapter, heading)) = link.split_once('#') {
+        let target = find(chapter, targets)?;
+        let (anchor, heading_text) =
+            target
+                .headings
+                .iter()
+                .find(|(a, _)| a == heading)
+                .ok_or(format!(
+                    "no heading `#{}` in chapter `{}`",
+                    heading, chapter
+                ))?;
+        (target, Some(anchor.clone()), heading_text.clone())
+    } else if let Some((chapter, item)) = link.split_once("::") {
+        let target = find(chapter, targets)?;
+        let (_, anchor) = target
+            .items
+            .iter()
+            .find(|(i, _)| i == item)
+            .ok_or(format!("no item `{}` in chapter `{}`", item, chapter))?;
+        (target, anchor.clone(), format!("`{}`", item))
+    } else {
+        let target = find(link, targets)?;
+        (target, None, target.title.clone())
+    };
+
+    let text = text.map(|t| t.to_string()).unwrap_or(default_text);
+    let file = match &chapter.file {
+        Some(file) => file,
+        None => return Ok(text),
+    };
+
+    let href = match anchor {
+        Some(anchor) if chapter.name == name => format!("#{}", anchor),
+        Some(anchor) => format!("{}#{}", markdown::relative(from, file), anchor),
+        None => markdown::relative(from, file),
+    };
+    Ok(format!("[{}]({})", text, href))
+}
+
+fn find<'a>(chapter: &str, targets: &'a [Target]) -> Result<&'a Target, String> {
+    targets
+        .iter()
+        .find(|t| t.name == chapter)
+        .ok_or(format!("unknown chapter `{}`", chapter))
+}
+
+// Name of the item defined by a line of code, if any.
+pub fn item_name(line: &str) -> Option<&str> {
+    let mut words = line.split_whitespace().peekable();
+    while let Some(w) = words.next() {
+        match w {
+            "pub" | "pub(crate)" | "async" | "unsafe" => continue,
+            "const" if matches!(words.peek(), Some(&"fn" | &"unsafe" | &"async")) => continue,
+            "fn" | "struct" | "enum" | "trait" | "type" | "const" | "static" | "mod" | "union"
+            | "macro_rules!" => {
+                let name = words.next()?;
+                let end = name
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(name.len());
+                return Some(&name[..end]).filter(|n| !n.is_empty());
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+
+    const TRAITS: &str = "//t # Traits
+//t
+//t ## Traits as bounds
+
+pub trait Notification {}
+
+//t ## Returning types
+
+fn ex_ret() {}
+";
+
+    fn targets() -> Vec<Target> {
+        let doc = compile::compile("traits.rs", TRAITS).unwrap();
+        vec![
+            Target::from_doc("traits", "Traits", Some("docs/traits.md".to_string()), &doc),
+            Target::from_markdown(
+                "intro",
+                "Introduction",
+                None,
+                "# Introduction\n\n## Getting started\n",
+            ),
+        ]
+    }
+
+    fn resolve_prose(prose: &str, name: &str, from: &str) -> Result<String, String> {
+        let mut doc = compile::compile("x.rs", prose)?;
+        resolve(&mut doc, "x.rs", name, from, &targets())?;
+        Ok(doc.to_markdown())
+    }
+
+    #[test]
+    fn targets_of_a_chapter() {
+        let t = &targets()[0];
+        assert_eq!(
+            t.headings,
+            [
+                ("traits".to_string(), "Traits".to_string()),
+                (
+                    "traits-as-bounds".to_string(),
+                    "Traits as bounds".to_string()
+                ),
+                ("returning-types".to_string(), "Returning types".to_string()),
+            ]
+        );
+        assert_eq!(
+            t.items,
+            [
+                (
+                    "Notification".to_string(),
+                    Some("traits-as-bounds".to_string())
+                ),
+                ("ex_ret".to_string(), Some("returning-types".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn links() {
+        let md = resolve_prose(
+            "//t See [[traits]], [[traits#traits-as-bounds]], [[traits::Notification]]\n\
+             //t and [[traits#returning-types|returns]], but not `[[traits]]`.\n",
+            "closures",
+            "docs/closures.md",
+        )
+        .unwrap();
+        assert_eq!(
+            md.trim(),
+            "See [Traits](traits.md), [Traits as bounds](traits.md#traits-as-bounds), \
+             [`Notification`](traits.md#traits-as-bounds)\n\
+             and [returns](traits.md#returning-types), but not `[[traits]]`."
+        );
+    }
+
+    #[test]
+    fn links_within_the_chapter_and_outside_the_build() {
+        let md = resolve_prose(
+            "//t [[traits#returning-types]], [[intro#getting-started]]\n",
+            "traits",
+            "book/src/traits.md",
+        )
+        .unwrap();
+        assert_eq!(
+            md.trim(),
+            "[Returning types](#returning-types), Getting started"
+        );
+
+        let md = resolve_prose("//t [[traits]]\n", "closures", "book/closures.md").unwrap();
+        assert_eq!(md.trim(), "[Traits](../docs/traits.md)");
+    }
+
+    #[test]
+    fn errors() {
+        for (prose, err) in [
+            ("//t\n//t [[nope]]\n", "x.rs:2: unknown chapter `nope`"),
+            (
+                "//t [[traits#nope]]\n",
+                "x.rs:1: no heading `#nope` in chapter `traits`",
+            ),
+            (
+                "//t [[traits::Nope]]\n",
+                "x.rs:1: no item `Nope` in chapter `traits`",
+            ),
+        ] {
+            assert_eq!(
+                resolve_prose(prose, "closures", "docs/closures.md").unwrap_err(),
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn item_names() {
+        assert_eq!(item_name("pub fn ex_use<T>(item: T) {"), Some("ex_use"));
+        assert_eq!(item_name("pub(crate) const unsafe fn f() {}"), Some("f"));
+        assert_eq!(item_name("impl<T> Pair<T> {"), None);
+        assert_eq!(item_name("    let x = 1;"), None);
+    }
+}
@@ -0,0 +1,448 @@
+use crate::config::Config;
+use crate::json::Value;
+use crate::{expect, markdown};
+
+// Rules checked over the `//t` prose of a chapter, the word rules over
+// the comments of the code too. Every rule can be disabled in the
+// configuration (`lint.disable`), or for a single line with a directive
+// on the line before it:
+//
+//     //t!lint allow=repeated-word
+pub const RULES: &[&str] = &[
+    "heading-jump",
+    "duplicate-heading",
+    "multiple-h1",
+    "trailing-whitespace",
+    "line-length",
+    "unbalanced-code",
+    "todo",
+    "repeated-word",
+    "echoed-word",
+];
+
+// Short words that are a typo when repeated one word apart, as in "to be
+// to mutate", unlike e.g. "one by one" or "the type the trait".
+const ECHOED: &[&str] = &["to", "of", "in", "on", "for", "is", "are", "be", "with"];
+
+const DIRECTIVE: &str = "//t!lint ";
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("file".to_string(), Value::String(self.file.clone())),
+            ("line".to_string(), Value::Number(self.line.to_string())),
+            ("rule".to_string(), Value::String(self.rule.to_string())),
+            ("message".to_string(), Value::String(self.message.clone())),
+        ])
+    }
+}
+
+pub struct Options {
+    pub disabled: Vec<String>,
+    pub max_line_length: usize,
+}
+
+impl Options {
+    pub fn from_config(config: &Config) -> Result<Options, String> {
+        let disabled = config.get_strings("lint.disable")?;
+        for rule in disabled.iter() {
+            if !RULES.contains(&rule.as_str()) {
+                return Err(format!("config `lint.disable`: unknown rule `{}`", rule));
+            }
+        }
+        let max_line_length = config.get_int("lint.max_line_length")?.unwrap_or(80) as usize;
+        Ok(Options {
+            disabled,
+            max_line_length,
+        })
+    }
+}
+
+// A line of prose, without the `//t` prefix.
+struct Prose<'a> {
+    line: usize,
+    raw: &'a str,
+    text: &'a str,
+}
+
+pub fn lint(fp: &str, content: &str, opts: &Options) -> Result<Vec<Diagnostic>, String> {
+    // prose blocks, i.e. runs of consecutive `//t` lines, and the rules
+    // allowed on each line
+    let mut blocks: Vec<Vec<Prose>> = Vec::new();
+    let mut comments: Vec<Vec<Prose>> = Vec::new();
+    let mut previous_is_comment = false;
+    let mut allowed: Vec<(usize, Vec<String>)> = Vec::new();
+    let mut previous_is_prose = false;
+    for (i, raw) in content.lines().enumerate() {
+        if let Some(args) = raw.trim_start().strip_prefix(DIRECTIVE) {
+            allowed.push((
+                i + 2,
+                parse_allow(args).map_err(|e| format!("{}:{}: {}", fp, i + 1, e))?,
+            ));
+        }
+
+        let text = match raw.strip_prefix("//t") {
+            Some(text) if !text.starts_with('!') => text.strip_prefix(' ').unwrap_or(text),
+            _ => {
+                previous_is_prose = false;
+                // comments on their own lines are paragraphs, up to an
+                // empty one, trailing comments stand alone
+                let comment = comment(raw);
+                if comment
+                    .is_none_or(|c| c.trim().is_empty() || !raw.trim_start().starts_with("//"))
+                {
+                    previous_is_comment = false;
+                }
+                if let Some(text) = comment.filter(|c| !c.trim().is_empty()) {
+                    if !previous_is_comment {
+                        comments.push(Vec::new());
+                    }
+                    comments.last_mut().unwrap().push(Prose {
+                        line: i + 1,
+                        raw,
+                        text,
+                    });
+                    previous_is_comment = raw.trim_start().starts_with("//");
+                }
+                continue;
+            }
+        };
+        if !previous_is_prose {
+            blocks.push(Vec::new());
+        }
+        previous_is_comment = false;
+        blocks.last_mut().unwrap().push(Prose {
+            line: i + 1,
+            raw,
+            text,
+        });
+        previous_is_prose = true;
+    }
+
+    let mut out: Vec<Diagnostic> = Vec::new();
+    let mut report = |line: usize, rule: &'static str, message: String| {
+        let suppressed = opts.disabled.iter().any(|r| r == rule)
+            || allowed
+                .iter()
+                .any(|(l, rules)| *l == line && rules.iter().any(|r| r == rule));
+        if !suppressed {
+            out.push(Diagnostic {
+                file: fp.to_string(),
+                line,
+                rule,
+                message,
+            });
+        }
+    };
+
+    let prose: Vec<&Prose> = blocks.iter().flatten().collect();
+    let texts: Vec<&str> = prose.iter().map(|p| p.text).collect();
+    let headings = markdown::headings(&texts);
+
+    let mut h1 = 0;
+    let mut seen: Vec<String> = Vec::new();
+    let mut previous_level = 0;
+    for h in headings.iter() {
+        let line = prose[h.line].line;
+        if previous_level > 0 && h.level > previous_level + 1 {
+            report(
+                line,
+                "heading-jump",
+                format!("heading level jumps from {} to {}", previous_level, h.level),
+            );
+        }
+        previous_level = h.level;
+
+        if h.level == 1 {
+            h1 += 1;
+            if h1 > 1 {
+                report(
+                    line,
+                    "multiple-h1",
+                    "more than one top-level heading".to_string(),
+                );
+            }
+        }
+
+        let key = h.text.to_lowercase();
+        if seen.contains(&key) {
+            report(
+                line,
+                "duplicate-heading",
+                format!("duplicate heading `{}`", h.text),
+            );
+        }
+        seen.push(key);
+
+        if h.text.to_uppercase().starts_with("TODO") {
+            report(line, "todo", format!("leftover `{}` section", h.text));
+        }
+    }
+
+    // code in prose (e.g. compiler output) is exempt from the line length
+    let mut fenced = false;
+    for p in prose.iter() {
+        if p.text.trim_start().starts_with("```") {
+            fenced = !fenced;
+        }
+        if p.raw.ends_with(char::is_whitespace) {
+            report(
+                p.line,
+                "trailing-whitespace",
+                "trailing whitespace".to_string(),
+            );
+        }
+        let len = p.raw.chars().count();
+        if len > opts.max_line_length && !fenced {
+            report(
+                p.line,
+                "line-length",
+                format!(
+                    "line is {} characters long (max {})",
+                    len, opts.max_line_length
+                ),
+            );
+        }
+    }
+
+    for block in blocks.iter() {
+        lint_block(block, &mut report);
+    }
+    for block in comments.iter() {
+        let mut words = Words::default();
+        for c in block {
+            for (i, part) in c.text.split('`').enumerate() {
+                match i % 2 {
+                    0 => words.check(c.line, part, &mut report),
+                    _ => words.reset(),
+                }
+            }
+        }
+    }
+
+    out.sort_by_key(|d| d.line);
+    Ok(out)
+}
+
+// Code fences and spans must be closed within the block, as the code
+// following it would end up inside them. Repeated words are looked for
+// in every paragraph outside of code.
+fn lint_block(block: &[Prose], report: &mut impl FnMut(usize, &'static str, String)) {
+    let mut fence: Option<usize> = None;
+    let mut span: Option<usize> = None;
+    let mut words = Words::default();
+    for p in block {
+        if p.text.trim_start().starts_with("```") {
+            fence = match fence {
+                None => Some(p.line),
+                Some(_) => None,
+            };
+            continue;
+        }
+        if fence.is_some() {
+            continue;
+        }
+        if p.text.trim().is_empty() {
+            if let Some(line) = span.take() {
+                report(line, "unbalanced-code", "unclosed inline code".to_string());
+            }
+            words.reset();
+            continue;
+        }
+
+        for (i, part) in p.text.split('`').enumerate() {
+            if i > 0 {
+                span = match span {
+                    None => Some(p.line),
+                    Some(_) => None,
+                };
+            }
+            match span {
+                Some(_) => words.reset(),
+                None => words.check(p.line, part, report),
+            }
+        }
+    }
+
+    if let Some(line) = fence {
+        report(line, "unbalanced-code", "unclosed code fence".to_string());
+    }
+    if let Some(line) = span {
+        report(line, "unbalanced-code", "unclosed inline code".to_string());
+    }
+}
+
+// Text of the comment of a line of code, doc comments included, but not
+// the prose and directives of steps.
+fn comment(line: &str) -> Option<&str> {
+    if line.trim_start().starts_with("//t") {
+        return None;
+    }
+    let text = &line[expect::comment_start(line)? + 2..];
+    Some(text.strip_prefix(['/', '!']).unwrap_or(text))
+}
+
+// The last two words of a paragraph, to find the repeated ones. Words
+// separated by punctuation (e.g. a full stop) are not repetitions.
+#[derive(Default)]
+struct Words {
+    last: Option<String>,
+    before_last: Option<String>,
+}
+
+impl Words {
+    fn reset(&mut self) {
+        self.last = None;
+        self.before_last = None;
+    }
+
+    fn check(
+        &mut self,
+        line: usize,
+        text: &str,
+        report: &mut impl FnMut(usize, &'static str, String),
+    ) {
+        for token in text.split_whitespace() {
+            let word = token.trim_matches(|c: char| !c.is_alphanumeric());
+            if word.is_empty() || !token.starts_with(char::is_alphanumeric) {
+                self.reset();
+                continue;
+            }
+            let word = word.to_lowercase();
+            if self.last.as_ref() == Some(&word) && word.chars().any(|c| c.is_alphabetic()) {
+                report(line, "repeated-word", format!("repeated word `{}`", word));
+            } else if self.before_last.as_ref() == Some(&word) && ECHOED.contains(&word.as_str()) {
+                report(
+                    line,
+                    "echoed-word",
+                    format!("word `{}` repeated one word apart", word),
+                );
+            }
+
+            match token.ends_with(char::is_alphanumeric) {
+                true => self.before_last = self.last.replace(word),
+                false => self.reset(),
+            }
+        }
+    }
+}
+
+fn parse_allow(args: &str) -> Result<Vec<String>, String> {
+    let rules = args
+        .trim()
+        .strip_prefix("allow=")
+        .ok_or(format!("expected `allow=<rule>`, found `{}`", args.trim()))?;
+
+    let rules: Vec<String> = rules.split(',').map(|r| r.trim().to_string()).collect();
+    for rule in rules.iter() {
+        if !RULES.contains(&rule.as_str()) {
+            return Err(format!("unknown lint rule `{}`", rule));
+        }
+    }
+    Ok(rules)
+}
+
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    Value::Array(diagnostics.iter().map(|d| d.to_json()).collect()).to_json()
+}
+
+// `file:line: rule: message`
+pub fn to_text(d: &Diagnostic) -> String {
+    format!("{}:{}: {}: {}", d.file, d.line, d.rule, d.message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(src: &str) -> Vec<(usize, &'static str)> {
+        let opts = Options {
+            disabled: Vec::new(),
+            max_line_length: 40,
+        };
+        let out = lint("x.rs", src, &opts).unwrap();
+        out.into_iter().map(|d| (d.line, d.rule)).collect()
+    }
+
+    #[test]
+    fn headings() {
+        let src = "//t # A\n//t\n//t ### B\n\n//t # A\n//t ## TODO\n";
+        assert_eq!(
+            run(src),
+            [
+                (3, "heading-jump"),
+                (5, "multiple-h1"),
+                (5, "duplicate-heading"),
+                (6, "todo"),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines() {
+        let src = "//t trailing \n//t a line longer than forty characters, by far\n//t ```\n//t a fenced line longer than forty characters\n//t ```\n";
+        assert_eq!(run(src), [(1, "trailing-whitespace"), (2, "line-length")]);
+    }
+
+    #[test]
+    fn unbalanced_code() {
+        let src = "//t a `span\n//t\n//t ```\n\nfn f() {}\n";
+        assert_eq!(run(src), [(1, "unbalanced-code"), (3, "unbalanced-code")]);
+    }
+
+    #[test]
+    fn repeated_words() {
+        let src = "//t the the end, `is is` ok.\n//t Ok. ok, fine; 1 1\n//t it is\n//t is to be to\n//t\n//t to be, to\n";
+        assert_eq!(
+            run(src),
+            [
+                (1, "repeated-word"),
+                (4, "repeated-word"),
+                (4, "echoed-word")
+            ]
+        );
+    }
+
+    #[test]
+    fn code_comments() {
+        let src = r#"fn f() {
+    // Note that the closure must be declared as mutable to be
+    // to mutate the captured values.
+    //
+    // to mutate
+    let s = "// the the"; // the the
+    /// doc doc
+    //t!lint allow=repeated-word
+    // a a
+    //t step prose prose
+}
+"#;
+        assert_eq!(
+            run(src),
+            [
+                (3, "echoed-word"),
+                (6, "repeated-word"),
+                (7, "repeated-word")
+            ]
+        );
+    }
+
+    #[test]
+    fn allow() {
+        let src = "//t!lint allow=repeated-word,line-length\n//t a a line longer than forty characters\n//t b b\n";
+        assert_eq!(run(src), [(3, "repeated-word")]);
+        assert_eq!(
+            parse_allow("allow=nope"),
+            Err("unknown lint rule `nope`".to_string())
+        );
+        assert!(parse_allow("deny=todo").is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use crate::Chapter;
+
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+    // index of the heading line
+    pub line: usize,
+}
+
+// ATX headings outside of fenced code blocks.
+pub fn headings(lines: &[&str]) -> Vec<Heading> {
+    let mut out = Vec::new();
+    let mut in_fence = false;
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+
+        let level = line.chars().take_while(|c| *c == '#').count();
+        if level == 0 || level > 6 {
+            continue;
+        }
+        let text = &line[level..];
+        if !text.starts_with(' ') && !text.is_empty() {
+            continue;
+        }
+
+        out.push(Heading {
+            level,
+            text: text.trim().to_string(),
+            line: i,
+        });
+    }
+
+    out
+}
+
+// GitHub-compatible anchor of a heading.
+pub fn slug(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+// Anchors of the headings, disambiguated with a numeric suffix
+// when repeated, as GitHub does.
+pub fn anchors(headings: &[Heading]) -> Vec<String> {
+    let mut seen: Vec<String> = Vec::new();
+    let mut out = Vec::with_capacity(headings.len());
+    for h in headings {
+        let base = slug(&h.text);
+        let mut anchor = base.clone();
+        let mut n = 0;
+        while seen.contains(&anchor) {
+            n += 1;
+            anchor = format!("{}-{}", base, n);
+        }
+        seen.push(anchor.clone());
+        out.push(anchor);
+    }
+    out
+}
+
+// Nested list of links to the headings below the chapter title, up to
+// `depth` levels. Further headings at the level of the title (e.g. a
+// second `#`) are listed as the first level.
+pub fn toc(headings: &[Heading], anchors: &[String], file: &str, depth: usize) -> Vec<String> {
+    let top = headings.iter().map(|h| h.level).min().unwrap_or(1);
+    let title = headings.iter().position(|h| h.level == top);
+
+    headings
+        .iter()
+        .zip(anchors)
+        .enumerate()
+        .filter(|(i, _)| Some(*i) != title)
+        .map(|(_, (h, a))| (h.level.max(top + 1) - top, h, a))
+        .filter(|(level, _, _)| *level <= depth)
+        .map(|(level, h, a)| {
+            let indent = "  ".repeat(level - 1);
+            format!("{}- [{}]({}#{})", indent, h.text, file, a)
+        })
+        .collect()
+}
+
+// Link from a file to another, both relative to the same directory.
+pub fn relative(from: &str, to: &str) -> String {
+    let from: Vec<&str> = from
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    let to: Vec<&str> = to
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+
+    let dir = &from[..from.len().saturating_sub(1)];
+    let common = dir
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut out: Vec<&str> = vec![".."; dir.len() - common];
+    out.extend(&to[common..]);
+    out.join("/")
+}
+
+// Book-level index: every chapter with the table of contents of its
+// headings, `links` are the paths of the chapters from the index.
+pub fn index(
+    book_title: &str,
+    chapters: &[Chapter],
+    links: &[String],
+    depth: usize,
+) -> Vec<String> {
+    let mut out = vec![format!("# {}", book_title), String::new()];
+    for (c, link) in chapters.iter().zip(links) {
+        let lines: Vec<&str> = c.content.lines().collect();
+        let headings = headings(&lines);
+        let anchors = anchors(&headings);
+
+        out.push(format!("- [{}]({})", c.title, link));
+        for l in toc(&headings, &anchors, link, depth) {
+            out.push(format!("  {}", l));
+        }
+    }
+    out.push(String::new());
+    out
+}
+
+// Text of the first top-level heading.
+pub fn title(content: &str) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let headings = headings(&lines);
+    let top = headings.iter().map(|h| h.level).min()?;
+    headings
+        .into_iter()
+        .find(|h| h.level == top)
+        .map(|h| h.text)
+}
+
+// First paragraph of prose after the title, joined on a single line.
+pub fn summary(content: &str) -> Option<String> {
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+    for line in content.lines() {
+        let l = line.trim();
+        if l.starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+
+        let is_prose = !in_fence
+            && !l.is_empty()
+            && !l.starts_with('#')
+            && !l.starts_with('|')
+            && !l.starts_with('<')
+            && !l.starts_with("- ");
+        if is_prose {
+            paragraph.push(l);
+        } else if !paragraph.is_empty() {
+            break;
+        }
+    }
+
+    if paragraph.is_empty() {
+        None
+    } else {
+        Some(paragraph.join(" "))
+    }
+}
+
+// Estimated reading time in minutes, at 200 words per minute. Collapsed
+// `<details>` blocks (e.g. assembly listings) are not counted.
+pub fn reading_time(content: &str) -> usize {
+    let mut words = 0;
+    let mut collapsed = false;
+    for line in content.lines() {
+        match line.trim() {
+            "<details>" => collapsed = true,
+            "</details>" => collapsed = false,
+            l if !collapsed => words += l.split_whitespace().count(),
+            _ => {}
+        }
+    }
+    words.div_ceil(200).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "# Traits
+
+Intro.
+
+## Defining a trait
+
+```rust
+# not a heading
+```
+
+### Default `impl`
+
+#Not a heading either
+
+## Defining a trait
+
+# Appendix
+";
+
+    fn parsed(content: &str) -> (Vec<Heading>, Vec<String>) {
+        let lines: Vec<&str> = content.lines().collect();
+        let headings = headings(&lines);
+        let anchors = anchors(&headings);
+        (headings, anchors)
+    }
+
+    #[test]
+    fn headings_outside_of_code() {
+        let (headings, _) = parsed(DOC);
+        let found: Vec<(usize, &str, usize)> = headings
+            .iter()
+            .map(|h| (h.level, h.text.as_str(), h.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (1, "Traits", 0),
+                (2, "Defining a trait", 4),
+                (3, "Default `impl`", 10),
+                (2, "Defining a trait", 14),
+                (1, "Appendix", 16),
+            ]
+        );
+    }
+
+    #[test]
+    fn slugs_and_anchors() {
+        assert_eq!(slug("Default `impl`"), "default-impl");
+        assert_eq!(slug(" Fn, FnMut & FnOnce "), "fn-fnmut--fnonce");
+        assert_eq!(slug("snake_case-names"), "snake_case-names");
+        assert_eq!(slug("Ünïcode"), "ünïcode");
+
+        let (_, anchors) = parsed(DOC);
+        assert_eq!(
+            anchors,
+            [
+                "traits",
+                "defining-a-trait",
+                "default-impl",
+                "defining-a-trait-1",
+                "appendix",
+            ]
+        );
+    }
+
+    #[test]
+    fn tables_of_contents() {
+        let (headings, anchors) = parsed(DOC);
+        assert_eq!(
+            toc(&headings, &anchors, "traits.md", 2),
+            [
+                "- [Defining a trait](traits.md#defining-a-trait)",
+                "  - [Default `impl`](traits.md#default-impl)",
+                "- [Defining a trait](traits.md#defining-a-trait-1)",
+                "- [Appendix](traits.md#appendix)",
+            ]
+        );
+        assert_eq!(
+            toc(&headings, &anchors, "", 1),
+            [
+                "- [Defining a trait](#defining-a-trait)",
+                "- [Defining a trait](#defining-a-trait-1)",
+                "- [Appendix](#appendix)",
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_links() {
+        assert_eq!(relative("docs/a.md", "docs/b.md"), "b.md");
+        assert_eq!(relative("./README.md", "docs/b.md"), "docs/b.md");
+        assert_eq!(relative("book/src/a.md", "docs/b.md"), "../../docs/b.md");
+    }
+
+    #[test]
+    fn title_and_summary() {
+        assert_eq!(title(DOC).as_deref(), Some("Traits"));
+        assert_eq!(title("Prose only."), None);
+        assert_eq!(
+            summary("# T\n\n- [A](#a)\n\nFirst\nparagraph.\n\nSecond.").as_deref(),
+            Some("First paragraph.")
+        );
+    }
+}
@@ -0,0 +1,94 @@
+// Turns the `code-tabs` blocks rendered by the handbook into tabs, with a
+// button for every block, labelled by its `data-label`.
+(function () {
+    "use strict";
+
+    function select(tabs, buttons, index) {
+        tabs.forEach(function (tab, i) {
+            tab.hidden = i !== index;
+            buttons[i].setAttribute("aria-selected", i === index ? "true" : "false");
+        });
+    }
+
+    document.querySelectorAll(".code-tabs").forEach(function (group) {
+        var tabs = Array.prototype.filter.call(group.children, function (el) {
+            return el.classList.contains("code-tab");
+        });
+        if (tabs.length === 0) {
+            return;
+        }
+
+        var bar = document.createElement("div");
+        bar.className = "code-tabs-bar";
+        bar.setAttribute("role", "tablist");
+        var buttons = tabs.map(function (tab, i) {
+            var button = document.createElement("button");
+            button.type = "button";
+            button.setAttribute("role", "tab");
+            button.textContent = tab.dataset.label || "Tab " + (i + 1);
+            button.addEventListener("click", function () {
+                select(tabs, buttons, i);
+            });
+            bar.appendChild(button);
+            return button;
+        });
+
+        group.insertBefore(bar, group.firstChild);
+        group.classList.add("js");
+        select(tabs, buttons, 0);
+    });
+})();
+
+// Shows the line numbers (`start-N`) and highlighted lines (`hl-N`,
+// `hl-N-M`) that `//t!code` sets as classes of the code blocks. Both are
+// drawn over the code, whose text is left untouched for copying.
+(function () {
+    "use strict";
+
+    document.querySelectorAll("pre > code").forEach(function (code) {
+        var start = null;
+        var ranges = [];
+        code.classList.forEach(function (c) {
+            var m = /^start-(\d+)$/.exec(c);
+            if (m) {
+                start = parseInt(m[1], 10);
+                return;
+            }
+            m = /^hl-(\d+)(?:-(\d+))?$/.exec(c);
+            if (m) {
+                ranges.push([parseInt(m[1], 10), parseInt(m[2] || m[1], 10)]);
+            }
+        });
+        if (start === null && ranges.length === 0) {
+            return;
+        }
+
+        code.classList.add("code-lines");
+        var top = getComputedStyle(code).paddingTop;
+        var at = function (line) {
+            return "calc(" + top + " + " + (line - 1) + "lh)";
+        };
+
+        ranges.forEach(function (range) {
+            var mark = document.createElement("span");
+            mark.className = "code-highlight";
+            mark.style.top = at(range[0]);
+            mark.style.height = range[1] - range[0] + 1 + "lh";
+            code.appendChild(mark);
+        });
+
+        if (start !== null) {
+            var count = code.textContent.replace(/\n$/, "").split("\n").length;
+            var numbers = [];
+            for (var i = 0; i < count; i++) {
+                numbers.push(start + i);
+            }
+            var gutter = document.createElement("span");
+            gutter.className = "code-gutter";
+            gutter.style.top = at(1);
+            gutter.dataset.lines = numbers.join("\n");
+            code.classList.add("code-numbered");
+            code.appendChild(gutter);
+        }
+    });
+})();
@@ -0,0 +1,612 @@
+use crate::compile::Doc;
+use crate::config::{self, Config};
+use crate::json::{self, Value};
+use crate::{Chapter, Format, links, markdown, preprocessor};
+use std::fs;
+use std::path::Path;
+
+pub const TITLE: &str = "Rust Handbook";
+
+// Placeholder expanded by the preprocessor with the compiled chapter:
+//
+//     {{#handbook src/chapters/traits.rs}}
+const INCLUDE: &str = "{{#handbook ";
+
+// Styles and scripts of the HTML the renderers emit for the book, e.g.
+// tabs and admonitions.
+const CSS: (&str, &str) = ("handbook.css", include_str!("mdbook.css"));
+const JS: (&str, &str) = ("handbook.js", include_str!("mdbook.js"));
+
+// A chapter is split in pages at every `#`/`##` heading following the
+// first one, the pages are nested under the chapter in the summary.
+struct Page {
+    title: String,
+    file: String,
+    // line of the chapter the page starts at
+    first: usize,
+    lines: Vec<String>,
+}
+
+// When `index` is set, an index page listing every page of the book
+// is added before the chapters.
+pub fn write_book(dir: &str, chapters: &[Chapter], index: bool) -> Result<(), String> {
+    let src = Path::new(dir).join("src");
+    if let Err(e) = fs::create_dir_all(&src) {
+        return Err(format!("creating dir: {}", e));
+    }
+
+    let book_toml = format!(
+        "[book]\ntitle = \"{}\"\nsrc = \"src\"\n\n[output.html]\nadditional-css = [\"{}\"]\nadditional-js = [\"{}\"]\n",
+        TITLE, CSS.0, JS.0
+    );
+    write(&Path::new(dir).join("book.toml"), &book_toml)?;
+    for (file, content) in [CSS, JS] {
+        write(&Path::new(dir).join(file), content)?;
+    }
+
+    let mut summary = vec!["# Summary".to_string(), String::new()];
+    let mut index_page = vec![format!("# {}", TITLE), String::new()];
+    if index {
+        summary.push("[Index](index.md)".to_string());
+        summary.push(String::new());
+    }
+
+    // links to anchors moved to another page, in the same chapter or in
+    // another one, must point to that page
+    let books: Vec<_> = chapters.iter().map(split).collect();
+    let anchors: Vec<(String, String, String)> = books
+        .iter()
+        .flat_map(|(pages, anchors)| {
+            anchors
+                .iter()
+                .map(|(a, page)| (pages[0].file.clone(), a.clone(), page.clone()))
+        })
+        .collect();
+
+    for (mut pages, _) in books {
+        let chapter = pages[0].file.clone();
+        for p in pages.iter_mut() {
+            for l in p.lines.iter_mut() {
+                if l.contains("](") {
+                    *l = retarget_anchors(l, &p.file, &chapter, &anchors);
+                }
+            }
+        }
+
+        for (i, page) in pages.iter().enumerate() {
+            let indent = if i == 0 { "" } else { "    " };
+            summary.push(format!("{}- [{}]({})", indent, page.title, page.file));
+            index_page.push(format!(
+                "{}- [{}]({})",
+                &indent[..indent.len() / 2],
+                page.title,
+                page.file
+            ));
+
+            let path = src.join(&page.file);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("creating dir: {}", e))?;
+            }
+            write(&path, &page.lines.join("\n"))?;
+        }
+    }
+
+    if index {
+        index_page.push(String::new());
+        write(&src.join("index.md"), &index_page.join("\n"))?;
+    }
+
+    summary.push(String::new());
+    write(&src.join("SUMMARY.md"), &summary.join("\n"))
+}
+
+// Files of the pages the chapter is split in, relative to the book
+// sources, with the line of the chapter content each one starts at.
+pub fn pages(chapter: &Chapter) -> Vec<(String, usize)> {
+    split(chapter)
+        .0
+        .into_iter()
+        .map(|p| (p.file, p.first))
+        .collect()
+}
+
+// Pages of the chapter, and the page holding every anchor.
+fn split(chapter: &Chapter) -> (Vec<Page>, Vec<(String, String)>) {
+    let name = &chapter.name;
+    let lines: Vec<&str> = chapter.content.lines().collect();
+    let headings: Vec<markdown::Heading> = markdown::headings(&lines)
+        .into_iter()
+        .filter(|h| h.level <= 2)
+        .collect();
+
+    let mut pages = vec![Page {
+        title: chapter.title.clone(),
+        file: format!("{}.md", name),
+        first: 0,
+        lines: Vec::new(),
+    }];
+
+    let mut next = headings.iter().skip(1).peekable();
+    for (i, line) in lines.iter().enumerate() {
+        if next.peek().is_some_and(|h| h.line == i) {
+            let h = next.next().unwrap();
+            let files: Vec<&str> = pages.iter().map(|p| p.file.as_str()).collect();
+            pages.push(Page {
+                title: h.text.clone(),
+                file: page_file(name, &markdown::slug(&h.text), &files),
+                first: i,
+                lines: Vec::new(),
+            });
+        }
+        pages.last_mut().unwrap().lines.push(line.to_string());
+    }
+
+    let all = markdown::headings(&lines);
+    let anchors = markdown::anchors(&all);
+    let mut anchor_pages: Vec<(String, String)> = Vec::new();
+    for (h, a) in all.iter().zip(anchors) {
+        let page = headings.iter().skip(1).filter(|p| p.line <= h.line).count();
+        anchor_pages.push((a, pages[page].file.clone()));
+    }
+
+    for p in pages.iter_mut() {
+        while p.lines.last().is_some_and(|l| l.trim().is_empty()) {
+            p.lines.pop();
+        }
+        p.lines.push(String::new());
+    }
+
+    (pages, anchor_pages)
+}
+
+// Headings with the same slug would overwrite each other's page: as for
+// anchors, the following ones get a `-1`, `-2`, ... suffix.
+fn page_file(chapter: &str, slug: &str, taken: &[&str]) -> String {
+    let mut file = format!("{}/{}.md", chapter, slug);
+    let mut n = 0;
+    while taken.contains(&file.as_str()) {
+        n += 1;
+        file = format!("{}/{}-{}.md", chapter, slug, n);
+    }
+    file
+}
+
+// Rewrites the links of a page of `chapter` to anchors, of the chapter
+// or to another one (`other.md#anchor`), given as (chapter, anchor, page),
+// and to other chapters, relative to the page.
+fn retarget_anchors(
+    line: &str,
+    file: &str,
+    chapter: &str,
+    anchors: &[(String, String, String)],
+) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(i) = rest.find("](") {
+        out.push_str(&rest[..i + 2]);
+        rest = &rest[i + 2..];
+
+        let end = rest.find(')').unwrap_or(rest.len());
+        let (target, anchor) = match rest[..end].split_once('#') {
+            Some(("", anchor)) => (chapter, Some(anchor)),
+            Some((target, anchor)) => (target, Some(anchor)),
+            None => (&rest[..end], None),
+        };
+        if !anchors.iter().any(|(c, _, _)| c == target) {
+            continue;
+        }
+
+        let page = anchor
+            .and_then(|anchor| anchors.iter().find(|(c, a, _)| c == target && a == anchor))
+            .map_or(target, |(_, _, page)| page);
+        let link = if page == file && anchor.is_some() {
+            String::new()
+        } else {
+            markdown::relative(file, page)
+        };
+        out.push_str(&link);
+        if let Some(anchor) = anchor {
+            out.push('#');
+            out.push_str(anchor);
+        }
+        rest = &rest[end..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn write(path: &Path, content: &str) -> Result<(), String> {
+    fs::write(path, content).map_err(|e| format!("writing {}: {}", path.display(), e))
+}
+
+// Expansions are plain markdown, so every renderer is supported.
+pub fn supports(_renderer: &str) -> bool {
+    true
+}
+
+// Runs the preprocessor on the `[context, book]` pair sent by mdBook,
+// returning the book to send back. Paths are relative to the book root,
+// where the configuration is read from too.
+pub fn preprocess(input: &str) -> Result<String, String> {
+    let (ctx, mut book) = match json::parse(input)? {
+        Value::Array(mut items) if items.len() == 2 => {
+            let book = items.pop().unwrap();
+            (items.pop().unwrap(), book)
+        }
+        _ => return Err("expected a [context, book] pair".to_string()),
+    };
+
+    let root = Path::new(ctx.get("root").and_then(|r| r.as_str()).unwrap_or("."));
+    let config = Config::load(&root.join(config::CONFIG_FILE).to_string_lossy())?;
+    let preprocessors = crate::preprocessors(&config, Format::Mdbook)?;
+
+    // every included chapter is compiled first: links between them point
+    // to the page including their target
+    let mut included: Vec<Included> = Vec::new();
+    let mut targets: Vec<links::Target> = Vec::new();
+    for_each_chapter(&mut book, &mut |page, content| {
+        for path in includes(content)? {
+            let fp = root.join(path).to_string_lossy().to_string();
+            let mut doc = crate::compile_chapter(&fp)?;
+            preprocessor::run_all(&mut doc, &preprocessors)
+                .map_err(|e| format!("{}: {}", fp, e))?;
+
+            let stem = Path::new(path).file_stem().unwrap_or_default();
+            let name = doc
+                .meta()
+                .slug
+                .clone()
+                .unwrap_or(stem.to_string_lossy().to_string());
+            targets.push(links::Target::from_doc(
+                &name,
+                &crate::title(&name, &doc),
+                page.map(|p| p.to_string()),
+                &doc,
+            ));
+            included.push(Included {
+                fp,
+                name,
+                page: page.unwrap_or("").to_string(),
+                doc,
+            });
+        }
+        Ok(())
+    })?;
+
+    // includes are met in the same order again
+    let mut included = included.into_iter();
+    for_each_chapter(&mut book, &mut |_, content| {
+        *content = expand(content, &mut |_| {
+            let Included {
+                fp,
+                name,
+                page,
+                mut doc,
+            } = included.next().unwrap();
+            crate::render_chapter(&mut doc, &fp, &name, &page, &targets, Format::Mdbook)?;
+            Ok(doc.to_markdown())
+        })?;
+        Ok(())
+    })?;
+    Ok(book.to_json())
+}
+
+// A chapter source included by a page of the book.
+struct Included {
+    fp: String,
+    name: String,
+    page: String,
+    doc: Doc,
+}
+
+// Calls `f` with the path and content of every chapter of the book.
+// Chapters are the objects with a name and a content, wherever they are
+// nested: this keeps working across changes of the book layout.
+fn for_each_chapter<F>(v: &mut Value, f: &mut F) -> Result<(), String>
+where
+    F: FnMut(Option<&str>, &mut String) -> Result<(), String>,
+{
+    match v {
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                for_each_chapter(item, f)?;
+            }
+        }
+        Value::Object(fields) => {
+            let field = |key: &str| {
+                fields
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .and_then(|(_, v)| v.as_str())
+                    .map(|v| v.to_string())
+            };
+            let name = field("name");
+            let path = field("path");
+
+            for (k, v) in fields.iter_mut() {
+                match (k.as_str(), v, &name) {
+                    ("content", Value::String(content), Some(name)) => {
+                        f(path.as_deref(), content)
+                            .map_err(|e| format!("chapter `{}`: {}", name, e))?;
+                    }
+                    (_, v, _) => for_each_chapter(v, f)?,
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+// Paths of the chapter sources included by a content.
+fn includes(content: &str) -> Result<Vec<&str>, String> {
+    let mut out = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(INCLUDE) {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or(format!("unterminated `{}`", INCLUDE.trim()))?;
+        out.push(rest[start + INCLUDE.len()..start + end].trim());
+        rest = &rest[start + end + 2..];
+    }
+    Ok(out)
+}
+
+// Replaces the includes of a content with the rendering of their chapter.
+fn expand(
+    content: &str,
+    render: &mut dyn FnMut(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find(INCLUDE) {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or(format!("unterminated `{}`", INCLUDE.trim()))?;
+
+        let path = rest[start + INCLUDE.len()..start + end].trim();
+        out.push_str(&rest[..start]);
+        out.push_str(render(path)?.trim_end());
+        rest = &rest[start + end + 2..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(content: &str) -> Chapter {
+        Chapter {
+            name: "traits".to_string(),
+            src: "src/chapters/traits.rs".to_string(),
+            title: "Traits".to_string(),
+            content: content.to_string(),
+            lines: Vec::new(),
+            file: "traits.md".to_string(),
+            meta: Default::default(),
+        }
+    }
+
+    // A book with the given (name, path, content) chapters, as sent by
+    // mdBook.
+    fn book(root: &Path, chapters: &[(&str, &str, &str)]) -> String {
+        let chapters: Vec<String> = chapters
+            .iter()
+            .map(|(name, path, content)| {
+                format!(
+                    r#"{{"Chapter":{{"name":{},"content":{},"number":[1],"sub_items":[],"path":{},"parent_names":[]}}}}"#,
+                    json::quote(name),
+                    json::quote(content),
+                    json::quote(path)
+                )
+            })
+            .collect();
+        format!(
+            r#"[{{"root":{},"config":{{"book":{{"title":"T"}}}},"renderer":"html","mdbook_version":"0.4.40"}},{{"sections":[{},"Separator"],"__non_exhaustive":null}}]"#,
+            json::quote(&root.to_string_lossy()),
+            chapters.join(",")
+        )
+    }
+
+    fn root(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("handbook-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        for (file, content) in files {
+            fs::write(root.join(file), content).unwrap();
+        }
+        root
+    }
+
+    // Content of the chapters of a preprocessed book.
+    fn contents(out: &str) -> Vec<String> {
+        let out = json::parse(out).unwrap();
+        match out.get("sections") {
+            Some(Value::Array(sections)) => sections
+                .iter()
+                .filter_map(|s| s.get("Chapter"))
+                .map(|c| {
+                    c.get("content")
+                        .and_then(|c| c.as_str())
+                        .unwrap()
+                        .to_string()
+                })
+                .collect(),
+            _ => panic!("no sections in {}", out.to_json()),
+        }
+    }
+
+    #[test]
+    fn preprocess_expands_chapters() {
+        let root = root(
+            "expand",
+            &[(
+                "diff.rs",
+                "//t # Diff\n\nfn a() {\n    //t!region x\n    let n = 1;\n    //t!endregion\n    //t!region y\n    let n = 2;\n    //t!endregion\n    println!(\"{}\", n);\n}\n\n//t!diff from=x to=y\n",
+            )],
+        );
+        let out = preprocess(&book(
+            &root,
+            &[(
+                "Diff",
+                "diff.md",
+                "Before\n\n{{#handbook diff.rs}}\n\nAfter\n",
+            )],
+        ))
+        .unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let out = json::parse(&out).unwrap();
+        let sections = match out.get("sections") {
+            Some(Value::Array(sections)) => sections,
+            _ => panic!("no sections in {}", out.to_json()),
+        };
+        assert_eq!(sections[1], Value::String("Separator".to_string()));
+        let chapter = sections[0].get("Chapter").unwrap();
+        assert_eq!(
+            chapter.get("path").and_then(|p| p.as_str()),
+            Some("diff.md")
+        );
+
+        let content = chapter.get("content").and_then(|c| c.as_str()).unwrap();
+        assert!(content.starts_with("Before\n\n# Diff\n"), "{}", content);
+        assert!(content.ends_with("\n\nAfter\n"), "{}", content);
+        assert!(content.contains("-let n = 1;\n+let n = 2;"), "{}", content);
+        assert!(!content.contains("//t"), "{}", content);
+    }
+
+    #[test]
+    fn preprocess_reports_the_chapter() {
+        let root = root(
+            "error",
+            &[
+                ("diff.rs", "//t!diff from=x to=y\n"),
+                ("links.rs", "//t # Links\n//t\n//t See [[nope]].\n"),
+            ],
+        );
+        let diff_err = preprocess(&book(
+            &root,
+            &[("Diff", "diff.md", "{{#handbook diff.rs}}")],
+        ))
+        .unwrap_err();
+        let links_err = preprocess(&book(
+            &root,
+            &[("Links", "links.md", "{{#handbook links.rs}}")],
+        ))
+        .unwrap_err();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(diff_err.starts_with("chapter `Diff`: "), "{}", diff_err);
+        assert!(
+            diff_err.ends_with("diff.rs:1: unknown region `x`"),
+            "{}",
+            diff_err
+        );
+        assert!(links_err.starts_with("chapter `Links`: "), "{}", links_err);
+        assert!(
+            links_err.ends_with("links.rs:3: unknown chapter `nope`"),
+            "{}",
+            links_err
+        );
+    }
+
+    #[test]
+    fn preprocess_links_chapters_and_reads_the_configuration() {
+        let root = root(
+            "links",
+            &[
+                (
+                    "handbook.toml",
+                    "[mdbook]\npreprocessors = [\"strip-emoji\"]\ntoc = true\n",
+                ),
+                (
+                    "a.rs",
+                    "//t # A\n//t\n//t ✅ See [[b#details]] and [[b]].\n",
+                ),
+                (
+                    "b.rs",
+                    "//t # B\n//t\n//t Intro.\n//t\n//t ## Details\n//t\n//t Text.\n",
+                ),
+            ],
+        );
+        let out = preprocess(&book(
+            &root,
+            &[
+                ("A", "a.md", "{{#handbook a.rs}}"),
+                ("B", "sub/b.md", "{{#handbook b.rs}}"),
+            ],
+        ))
+        .unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let contents = contents(&out);
+        assert!(
+            contents[0].contains("See [Details](sub/b.md#details) and [B](sub/b.md)."),
+            "{}",
+            contents[0]
+        );
+        assert!(!contents[0].contains('✅'), "{}", contents[0]);
+        assert!(
+            contents[1].contains("- [Details](#details)"),
+            "{}",
+            contents[1]
+        );
+    }
+
+    #[test]
+    fn preprocess_rejects_other_input() {
+        assert!(preprocess(r#"{"sections":[]}"#).is_err());
+        assert!(preprocess("[{}]").is_err());
+    }
+
+    #[test]
+    fn pages_with_the_same_slug() {
+        let c =
+            chapter("# Traits\n\n## Examples\n\nfirst\n\n## Examples\n\nsecond\n\n## Examples-1\n");
+        let files: Vec<String> = pages(&c).into_iter().map(|(f, _)| f).collect();
+        assert_eq!(
+            files,
+            [
+                "traits.md",
+                "traits/examples.md",
+                "traits/examples-1.md",
+                "traits/examples-1-1.md",
+            ]
+        );
+    }
+
+    #[test]
+    fn anchors_follow_their_page() {
+        let c = chapter("# Traits\n\n## Bounds\n\n### Where\n\n## Bounds\n");
+        let (_, anchors) = split(&c);
+        let page = |a: &str| {
+            anchors
+                .iter()
+                .find(|(x, _)| x == a)
+                .map(|(_, p)| p.as_str())
+        };
+        assert_eq!(page("traits"), Some("traits.md"));
+        assert_eq!(page("where"), Some("traits/bounds.md"));
+        assert_eq!(page("bounds-1"), Some("traits/bounds-1.md"));
+    }
+
+    #[test]
+    fn theme_is_wired() {
+        let dir = std::env::temp_dir().join(format!("handbook-theme-{}", std::process::id()));
+        let dir_s = dir.to_string_lossy().to_string();
+        write_book(&dir_s, &[chapter("# Traits\n")], false).unwrap();
+        let book_toml = fs::read_to_string(dir.join("book.toml")).unwrap();
+        let css = fs::read_to_string(dir.join("handbook.css")).unwrap();
+        let js = fs::read_to_string(dir.join("handbook.js")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(book_toml.contains("additional-css = [\"handbook.css\"]"));
+        assert!(book_toml.contains("additional-js = [\"handbook.js\"]"));
+        assert!(css.contains(".code-tabs") && css.contains(".code-compare"));
+        assert!(js.contains("code-tabs"));
+    }
+}
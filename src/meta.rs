@@ -0,0 +1,296 @@
+use crate::json;
+
+// Chapter metadata, declared at the top of a `.rs` chapter one key per
+// directive, lists being comma separated:
+//
+//     //t!meta title: Closures
+//     //t!meta tags: closures, fn-traits
+//
+// Markdown chapters can declare the same keys in a YAML front matter.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Meta {
+    pub title: Option<String>,
+    pub slug: Option<String>,
+    pub summary: Option<String>,
+    pub authors: Vec<String>,
+    pub tags: Vec<String>,
+    pub prerequisites: Vec<String>,
+    pub difficulty: Option<String>,
+    pub status: Option<String>,
+}
+
+pub const DIRECTIVE: &str = "//t!meta ";
+
+impl Meta {
+    // Sets a key from a directive, lists being comma separated.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.assign(key, Value::Plain(value.trim().to_string()))
+    }
+
+    fn assign(&mut self, key: &str, value: Value) -> Result<(), String> {
+        let scalar = |value: Value| -> Result<Option<String>, String> {
+            match value {
+                Value::Plain(v) | Value::Quoted(v) => Ok(Some(v)),
+                Value::List(_) => Err(format!("`{}` expects a single value", key.trim())),
+            }
+        };
+        let list = |value: Value| -> Vec<String> {
+            match value {
+                Value::Plain(v) => v
+                    .split(',')
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty())
+                    .collect(),
+                Value::Quoted(v) => vec![v],
+                Value::List(items) => items,
+            }
+        };
+
+        match key.trim() {
+            "title" => self.title = scalar(value)?,
+            "slug" => self.slug = scalar(value)?,
+            "summary" => self.summary = scalar(value)?,
+            "authors" => self.authors = list(value),
+            "tags" => self.tags = list(value),
+            "prerequisites" => self.prerequisites = list(value),
+            "difficulty" => self.difficulty = scalar(value)?,
+            "status" => self.status = scalar(value)?,
+            key => return Err(format!("unknown metadata key `{}`", key)),
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Meta::default()
+    }
+
+    // YAML front matter, with strings written as JSON ones (which are
+    // valid YAML) to avoid quoting issues.
+    pub fn front_matter(&self) -> Vec<String> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let mut out = vec!["---".to_string()];
+        let scalars = [
+            ("title", &self.title),
+            ("slug", &self.slug),
+            ("summary", &self.summary),
+            ("difficulty", &self.difficulty),
+            ("status", &self.status),
+        ];
+        for (key, value) in scalars {
+            if let Some(v) = value {
+                out.push(format!("{}: {}", key, json::quote(v)));
+            }
+        }
+
+        let lists = [
+            ("authors", &self.authors),
+            ("tags", &self.tags),
+            ("prerequisites", &self.prerequisites),
+        ];
+        for (key, values) in lists {
+            if !values.is_empty() {
+                let quoted: Vec<String> = values.iter().map(|v| json::quote(v)).collect();
+                out.push(format!("{}: [{}]", key, quoted.join(", ")));
+            }
+        }
+
+        out.push("---".to_string());
+        out
+    }
+}
+
+// A front matter value: plain scalars of list keys are still comma
+// separated, as in directives.
+#[derive(Debug, PartialEq)]
+enum Value {
+    Plain(String),
+    Quoted(String),
+    List(Vec<String>),
+}
+
+// Splits the YAML front matter of a markdown file, if any, from its body.
+// Only `key: value` pairs are supported, with lists in flow style and
+// strings plain, single quoted or double quoted with JSON escapes.
+pub fn parse_front_matter(content: &str) -> Result<(Meta, &str), String> {
+    let mut meta = Meta::default();
+    let rest = match content.strip_prefix("---\n") {
+        Some(rest) => rest,
+        None => return Ok((meta, content)),
+    };
+
+    let end = rest.find("\n---").ok_or("unterminated front matter")?;
+    for (i, line) in rest[..end].lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let pos = format!("front matter line {}", i + 2);
+        let (key, value) = line
+            .split_once(':')
+            .ok_or(format!("{}: expected `key: value`", pos))?;
+        let value = parse_value(value.trim()).map_err(|e| format!("{}: {}", pos, e))?;
+        meta.assign(key, value)
+            .map_err(|e| format!("{}: {}", pos, e))?;
+    }
+
+    let body = &rest[end + 4..];
+    Ok((meta, body.strip_prefix('\n').unwrap_or(body)))
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    let inner = match s.strip_prefix('[') {
+        Some(inner) => inner.strip_suffix(']').ok_or("unterminated list")?,
+        None if s.starts_with(['"', '\'']) => return Ok(Value::Quoted(parse_string(s)?)),
+        None => return Ok(Value::Plain(s.to_string())),
+    };
+
+    let mut items = Vec::new();
+    let mut rest = inner.trim();
+    while !rest.is_empty() {
+        let len = match rest.chars().next() {
+            Some(q @ ('"' | '\'')) => string_len(rest, q).ok_or("unterminated string")?,
+            _ => rest.find(',').unwrap_or(rest.len()),
+        };
+        let item = rest[..len].trim();
+        items.push(match item.starts_with(['"', '\'']) {
+            true => parse_string(item)?,
+            false => item.to_string(),
+        });
+
+        rest = rest[len..].trim_start();
+        rest = match rest.strip_prefix(',') {
+            Some(r) => r.trim_start(),
+            None if rest.is_empty() => rest,
+            None => return Err(format!("expected `,` before `{}`", rest)),
+        };
+    }
+    Ok(Value::List(items))
+}
+
+// Length of the quoted string at the start of `s`, quotes included.
+fn string_len(s: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            c if c == quote => {
+                // `''` is an escaped quote in single quoted strings
+                if quote == '\'' && s[i + 1..].starts_with('\'') {
+                    escaped = true;
+                    continue;
+                }
+                return Some(i + 1);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_string(s: &str) -> Result<String, String> {
+    let quote = s.chars().next().unwrap_or('"');
+    if string_len(s, quote) != Some(s.len()) {
+        return Err(format!("invalid string `{}`", s));
+    }
+    match quote {
+        '"' => match json::parse(s) {
+            Ok(json::Value::String(v)) => Ok(v),
+            _ => Err(format!("invalid string `{}`", s)),
+        },
+        _ => Ok(s[1..s.len() - 1].replace("''", "'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let meta = Meta {
+            title: Some("Traits, \"and\" more: \\o/".to_string()),
+            summary: Some("a, b".to_string()),
+            authors: vec!["O'Neil, Jr.".to_string(), "anBertoli".to_string()],
+            tags: vec!["a,b".to_string(), "[c]".to_string()],
+            prerequisites: vec!["traits".to_string()],
+            ..Default::default()
+        };
+        let content = meta.front_matter().join("\n") + "\n# Body\n";
+        assert_eq!(parse_front_matter(&content), Ok((meta, "# Body\n")));
+    }
+
+    #[test]
+    fn scalars_are_kept() {
+        let (meta, _) = parse_front_matter(
+            "---\ntitle: Traits, generics,and more\nsummary: 'it''s [here]'\n---\n",
+        )
+        .unwrap();
+        assert_eq!(meta.title.as_deref(), Some("Traits, generics,and more"));
+        assert_eq!(meta.summary.as_deref(), Some("it's [here]"));
+    }
+
+    #[test]
+    fn lists() {
+        let (meta, body) =
+            parse_front_matter("---\ntags: a, b\nauthors: ['x, y', \"z\", w ,]\n---\nbody")
+                .unwrap();
+        assert_eq!(meta.tags, ["a", "b"]);
+        assert_eq!(meta.authors, ["x, y", "z", "w"]);
+        assert_eq!(body, "body");
+
+        let (meta, _) = parse_front_matter("---\ntags: \"a, b\"\n---\n").unwrap();
+        assert_eq!(meta.tags, ["a, b"]);
+    }
+
+    #[test]
+    fn no_front_matter() {
+        assert_eq!(
+            parse_front_matter("# Title\n---\n"),
+            Ok((Meta::default(), "# Title\n---\n"))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        for (src, err) in [
+            ("---\ntitle: x\n", "unterminated front matter"),
+            (
+                "---\n\ntitle\n---\n",
+                "front matter line 3: expected `key: value`",
+            ),
+            (
+                "---\nkey: x\n---\n",
+                "front matter line 2: unknown metadata key `key`",
+            ),
+            (
+                "---\ntitle: [a]\n---\n",
+                "front matter line 2: `title` expects a single value",
+            ),
+            (
+                "---\ntags: [a\n---\n",
+                "front matter line 2: unterminated list",
+            ),
+            (
+                "---\ntags: [\"a]\n---\n",
+                "front matter line 2: unterminated string",
+            ),
+            (
+                "---\ntags: [\"a\" b]\n---\n",
+                "front matter line 2: expected `,` before `b`",
+            ),
+            (
+                "---\ntitle: \"a\" b\n---\n",
+                "front matter line 2: invalid string `\"a\" b`",
+            ),
+            (
+                "---\ntitle: \"\\x\"\n---\n",
+                "front matter line 2: invalid string `\"\\x\"`",
+            ),
+        ] {
+            assert_eq!(parse_front_matter(src), Err(err.to_string()), "{}", src);
+        }
+    }
+}
@@ -0,0 +1,347 @@
+use crate::compile::{Doc, Section};
+use crate::markdown;
+use crate::rustfmt;
+
+// Preprocessors rewrite a compiled chapter before it is rendered. They run
+// in registration order: first the ones listed in the configuration for
+// the output format, then the ones registered in code.
+pub trait Preprocessor {
+    fn name(&self) -> &str;
+    fn run(&self, doc: &mut Doc) -> Result<(), String>;
+}
+
+// Built-in preprocessors, as listed in the configuration:
+//
+//     preprocessors = ["strip-emoji", "shift-headings=1"]
+pub fn from_spec(spec: &str) -> Result<Box<dyn Preprocessor>, String> {
+    let (name, arg) = match spec.split_once('=') {
+        Some((name, arg)) => (name.trim(), Some(arg.trim())),
+        None => (spec.trim(), None),
+    };
+
+    match (name, arg) {
+        ("strip-emoji", None) => Ok(Box::new(StripEmoji)),
+        ("rustfmt", None) => Ok(Box::new(Rustfmt)),
+        ("shift-headings", Some(by)) => {
+            let by = by
+                .parse::<i32>()
+                .map_err(|_| format!("preprocessor `{}`: invalid shift `{}`", name, by))?;
+            Ok(Box::new(ShiftHeadings { by }))
+        }
+        _ => Err(format!("unknown preprocessor `{}`", spec)),
+    }
+}
+
+pub fn run_all(doc: &mut Doc, preprocessors: &[Box<dyn Preprocessor>]) -> Result<(), String> {
+    for p in preprocessors {
+        p.run(doc)
+            .map_err(|e| format!("preprocessor `{}`: {}", p.name(), e))?;
+    }
+    Ok(())
+}
+
+// Removes emoji from prose and code. Generated sections are left
+// untouched, since there emoji are values (e.g. ✅/🚫 table cells).
+pub struct StripEmoji;
+
+impl Preprocessor for StripEmoji {
+    fn name(&self) -> &str {
+        "strip-emoji"
+    }
+
+    fn run(&self, doc: &mut Doc) -> Result<(), String> {
+        for s in doc.sections_mut() {
+            if let Section::Generated { .. } = s {
+                continue;
+            }
+            for l in s.lines_mut().iter_mut() {
+                if l.chars().any(is_emoji) {
+                    *l = strip_emoji(l);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x2600..=0x27BF
+        | 0x2B00..=0x2BFF
+        | 0x1F000..=0x1FAFF
+        | 0xFE0F
+        | 0x200D)
+}
+
+// Drops the emoji together with the space following it.
+fn strip_emoji(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if !is_emoji(c) {
+            out.push(c);
+            continue;
+        }
+        while chars.peek().is_some_and(|c| is_emoji(*c)) {
+            chars.next();
+        }
+        if chars.peek() == Some(&' ') {
+            chars.next();
+        }
+    }
+    out
+}
+
+// Formats code sections with the local `rustfmt`. Sections rustfmt can't
+// handle, or every section if it's not installed, are left as written. A
+// `//t!nofmt` directive right before a section opts it out, e.g. for
+// examples aligned on purpose, and so do highlighted or numbered lines,
+// which refer to the code as written.
+pub struct Rustfmt;
+
+const NOFMT_DIRECTIVE: &str = "//t!nofmt";
+
+impl Preprocessor for Rustfmt {
+    fn name(&self) -> &str {
+        "rustfmt"
+    }
+
+    fn run(&self, doc: &mut Doc) -> Result<(), String> {
+        let mut nofmt = false;
+        for s in doc.sections_mut() {
+            let lines = match s {
+                Section::Directive { lines, .. } => {
+                    nofmt |= lines.iter().any(|l| l.trim_end() == NOFMT_DIRECTIVE);
+                    continue;
+                }
+                Section::Code { lines, attrs, .. }
+                    if !nofmt && attrs.highlight.is_empty() && attrs.start.is_none() =>
+                {
+                    lines
+                }
+                // lifted out of the code that follows
+                Section::Callout { .. } => continue,
+                _ => {
+                    nofmt = false;
+                    continue;
+                }
+            };
+
+            // the code between the fences
+            if lines.len() < 2 {
+                continue;
+            }
+            let end = lines.len() - 1;
+            if let Some(formatted) = rustfmt::format(&lines[1..end]) {
+                lines.splice(1..end, formatted);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Shifts the level of prose headings, clamped to the 1-6 range, e.g. to
+// nest a chapter under another page.
+pub struct ShiftHeadings {
+    pub by: i32,
+}
+
+impl Preprocessor for ShiftHeadings {
+    fn name(&self) -> &str {
+        "shift-headings"
+    }
+
+    fn run(&self, doc: &mut Doc) -> Result<(), String> {
+        for s in doc.sections_mut() {
+            let lines = match s {
+                Section::Comment { lines, .. } => lines,
+                _ => continue,
+            };
+
+            let refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+            let headings = markdown::headings(&refs);
+            for h in headings {
+                let level = (h.level as i32 + self.by).clamp(1, 6) as usize;
+                lines[h.line] = format!("{} {}", "#".repeat(level), h.text);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Table of contents of the chapter headings below the title, linking to
+// their anchors. It's placed at every `//t!toc` directive, which can set
+// the number of levels with `//t!toc depth=<n>`, or when there are none
+// and `auto` is set, after the paragraph following the title, whether
+// the two are separated in the source or not.
+pub struct Toc {
+    pub depth: usize,
+    pub auto: bool,
+}
+
+const TOC_DIRECTIVE: &str = "//t!toc";
+
+impl Preprocessor for Toc {
+    fn name(&self) -> &str {
+        "toc"
+    }
+
+    fn run(&self, doc: &mut Doc) -> Result<(), String> {
+        let mut targets: Vec<(usize, usize)> = Vec::new();
+        for s in doc.sections() {
+            if let Section::Directive { start, lines } = s {
+                for (i, l) in lines.iter().enumerate() {
+                    if let Some(args) = l.strip_prefix(TOC_DIRECTIVE) {
+                        targets.push((start + i, toc_depth(args, self.depth)?));
+                    }
+                }
+            }
+        }
+
+        if targets.is_empty() {
+            match intro_end(doc) {
+                Some(line) if self.auto => targets.push((line, self.depth)),
+                _ => return Ok(()),
+            }
+        }
+
+        let mut headings = Vec::new();
+        for s in doc.sections() {
+            if let Section::Comment { lines, .. } = s {
+                let refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+                headings.extend(markdown::headings(&refs));
+            }
+        }
+
+        let anchors = markdown::anchors(&headings);
+        for (line, depth) in targets.into_iter().rev() {
+            let toc = markdown::toc(&headings, &anchors, "", depth);
+            if !toc.is_empty() {
+                doc.insert_after(line, toc);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Source line ending the paragraph right after the first heading, or the
+// heading itself when none follows. Prose going on after the paragraph is
+// split into a section of its own, for the TOC to go in between.
+fn intro_end(doc: &mut Doc) -> Option<usize> {
+    let sections = doc.sections();
+    let (idx, heading) = sections.iter().enumerate().find_map(|(i, s)| match s {
+        Section::Comment { lines, .. } => {
+            let refs: Vec<&str> = lines.iter().map(|l| l.as_str()).collect();
+            markdown::headings(&refs).first().map(|h| (i, h.line))
+        }
+        _ => None,
+    })?;
+    let heading_line = sections[idx].start() + heading;
+
+    // the paragraph in the section of the heading, or starting the next one
+    let paragraph = |s: &Section, from: usize| match s {
+        Section::Comment { lines, .. } => lines
+            .iter()
+            .enumerate()
+            .skip(from)
+            .find(|(_, l)| !l.trim().is_empty())
+            .filter(|(_, l)| !l.starts_with('#') && !l.trim_start().starts_with("```"))
+            .map(|(i, _)| i),
+        _ => None,
+    };
+    let (idx, first) = match paragraph(&sections[idx], heading + 1) {
+        Some(first) => (idx, first),
+        None => match sections.get(idx + 1).and_then(|s| paragraph(s, 0)) {
+            Some(first) => (idx + 1, first),
+            None => return Some(heading_line),
+        },
+    };
+
+    let (start, lines) = match &mut doc.sections_mut()[idx] {
+        Section::Comment { start, lines } => (*start, lines),
+        _ => unreachable!(),
+    };
+    let end = lines[first..]
+        .iter()
+        .position(|l| l.trim().is_empty())
+        .map_or(lines.len(), |n| first + n);
+    let mut rest = lines.split_off(end);
+    let blank = rest.iter().take_while(|l| l.trim().is_empty()).count();
+    if blank < rest.len() {
+        let lines = rest.split_off(blank);
+        let section = Section::Comment {
+            start: start + end + blank,
+            lines,
+        };
+        doc.insert(idx + 1, section);
+    }
+    Some(start + end - 1)
+}
+
+fn toc_depth(args: &str, default: usize) -> Result<usize, String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return Ok(default);
+    }
+
+    args.strip_prefix("depth=")
+        .and_then(|d| d.parse::<usize>().ok())
+        .ok_or(format!("invalid arguments `{}`", args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile;
+
+    #[test]
+    fn rustfmt_keeps_highlighted_code() {
+        let src = "//t Prose.\n\nfn a()   {  }\n\n//t!code hl=1\nfn b()   {  }\n\n//t!code start=3\nfn c()   {  }\n";
+        let mut doc = compile::compile("x.rs", src).unwrap();
+
+        Rustfmt.run(&mut doc).unwrap();
+        let md = doc.to_markdown();
+        if rustfmt::available() {
+            assert!(md.contains("fn a() {}"), "{}", md);
+        }
+        assert!(md.contains("fn b()   {  }"), "{}", md);
+        assert!(md.contains("fn c()   {  }"), "{}", md);
+    }
+
+    #[test]
+    fn rustfmt_skips_nofmt_code() {
+        let src =
+            "//t Prose.\n\n//t!nofmt\nconst A: u8   = 1;\n\n//t Prose.\n\nconst B: u8   = 2;\n";
+        let mut doc = compile::compile("x.rs", src).unwrap();
+
+        Rustfmt.run(&mut doc).unwrap();
+        let md = doc.to_markdown();
+        assert!(md.contains("const A: u8   = 1;"), "{}", md);
+        if rustfmt::available() {
+            assert!(md.contains("const B: u8 = 2;"), "{}", md);
+        }
+    }
+
+    #[test]
+    fn toc_after_the_intro() {
+        let toc = Toc {
+            depth: 2,
+            auto: true,
+        };
+        let want = "# T\n\nIntro,\ncontinued.\n\n- [A](#a)\n\nMore.\n\n## A\n\n";
+        for src in [
+            "//t # T\n//t\n//t Intro,\n//t continued.\n//t\n//t More.\n\n//t ## A\n",
+            "//t # T\n\n//t Intro,\n//t continued.\n//t\n//t More.\n\n//t ## A\n",
+            "//t # T\n\n//t Intro,\n//t continued.\n\n//t More.\n\n//t ## A\n",
+        ] {
+            let mut doc = compile::compile("x.rs", src).unwrap();
+            toc.run(&mut doc).unwrap();
+            assert_eq!(doc.to_markdown(), want, "{}", src);
+        }
+
+        let mut doc = compile::compile("x.rs", "//t # T\n\n//t ## A\n").unwrap();
+        toc.run(&mut doc).unwrap();
+        assert_eq!(doc.to_markdown(), "# T\n\n- [A](#a)\n\n## A\n\n");
+    }
+}
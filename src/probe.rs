@@ -0,0 +1,127 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const PROBE_DIR: &str = "./target/probes";
+
+pub fn name(fp: &str, kind: &str) -> String {
+    let stem = fp.rsplit('/').next().unwrap_or(fp).trim_end_matches(".rs");
+    format!("{}_{}", stem, kind)
+}
+
+// Name of the top-level function each source line belongs to, if the
+// function takes no arguments and can be called from a generated main.
+pub fn callable_fns(lines: &[&str]) -> Vec<Option<String>> {
+    let mut out = Vec::with_capacity(lines.len());
+    let mut current: Option<String> = None;
+    for line in lines {
+        if starts_item(line) {
+            current = fn_name(line);
+        }
+        out.push(current.clone());
+    }
+    out
+}
+
+pub fn run(name: &str, src: &str) -> Result<String, String> {
+    let (out, bin) = rustc(name, src, &["--crate-type", "bin"])?;
+    if !out.status.success() {
+        return Err(format!(
+            "probe {} doesn't compile:\n{}",
+            name,
+            String::from_utf8_lossy(&out.stderr)
+        ));
+    }
+
+    let out = Command::new(&bin)
+        .output()
+        .map_err(|e| format!("running probe {}: {}", name, e))?;
+
+    if !out.status.success() {
+        return Err(format!(
+            "probe {} failed:\n{}",
+            name,
+            String::from_utf8_lossy(&out.stderr)
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+// Compiles the probe as an optimized library, returning the assembly.
+pub fn asm(name: &str, src: &str, crate_name: &str) -> Result<String, String> {
+    let mut args = vec![
+        "--crate-type",
+        "lib",
+        "--crate-name",
+        crate_name,
+        "--emit",
+        "asm",
+        "-O",
+    ];
+    if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+        args.extend(["-C", "llvm-args=-x86-asm-syntax=intel"]);
+    }
+
+    let (out, path) = rustc(name, src, &args)?;
+    if !out.status.success() {
+        return Err(format!(
+            "probe {} doesn't compile:\n{}",
+            name,
+            String::from_utf8_lossy(&out.stderr)
+        ));
+    }
+
+    fs::read_to_string(&path).map_err(|e| e.to_string())
+}
+
+// Type-checks the probe as a library, returning whether it compiles
+// together with the compiler diagnostics.
+pub fn check(name: &str, src: &str) -> Result<(bool, String), String> {
+    let (out, _) = rustc(name, src, &["--crate-type", "lib", "--emit", "metadata"])?;
+    let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+    Ok((out.status.success(), stderr))
+}
+
+fn rustc(name: &str, src: &str, args: &[&str]) -> Result<(Output, PathBuf), String> {
+    let dir = PathBuf::from(PROBE_DIR);
+    fs::create_dir_all(&dir).map_err(|e| format!("creating dir: {}", e))?;
+
+    let src_path = dir.join(format!("{}.rs", name));
+    let out_path = dir.join(name);
+    fs::write(&src_path, src).map_err(|e| e.to_string())?;
+
+    let out = Command::new(rustc_bin())
+        .args(["--edition", "2024", "-A", "warnings"])
+        .args(args)
+        .arg("-o")
+        .arg(&out_path)
+        .arg(&src_path)
+        .output()
+        .map_err(|e| format!("running rustc: {}", e))?;
+
+    Ok((out, out_path))
+}
+
+fn rustc_bin() -> String {
+    std::env::var("RUSTC").unwrap_or("rustc".to_string())
+}
+
+fn starts_item(line: &str) -> bool {
+    !line.is_empty()
+        && !line.starts_with(char::is_whitespace)
+        && !line.starts_with("//")
+        && !line.starts_with('}')
+}
+
+fn fn_name(line: &str) -> Option<String> {
+    let rest = line
+        .strip_prefix("fn ")
+        .or_else(|| line.strip_prefix("pub fn "))?;
+    let (name, rest) = rest.split_once('(')?;
+    if rest.starts_with(')') && !name.contains('<') {
+        Some(name.trim().to_string())
+    } else {
+        None
+    }
+}
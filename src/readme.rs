@@ -0,0 +1,145 @@
+use crate::{Chapter, markdown, region};
+
+// Fills the `chapters` region of the readme with every chapter, `links`
+// being the paths of the chapters from the readme, and the `material`
+// region with the standalone files, given as (title, path).
+pub fn write(
+    fp: &str,
+    chapters: &[Chapter],
+    links: &[String],
+    material: &[(&str, &str)],
+) -> Result<(), String> {
+    region::fill(fp, "chapters", &chapter_list(fp, chapters, links))?;
+    region::fill(fp, "material", &material_list(fp, material))
+}
+
+// An entry per chapter: title, reading time, difficulty and source, then
+// the summary, prerequisites and tags.
+fn chapter_list(fp: &str, chapters: &[Chapter], links: &[String]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (c, link) in chapters.iter().zip(links) {
+        let mut line = format!(
+            "- **[{}]({})** · {} min read",
+            c.title,
+            link,
+            markdown::reading_time(&c.content)
+        );
+        if let Some(difficulty) = &c.meta.difficulty {
+            line.push_str(&format!(" · {}", difficulty));
+        }
+        let source = markdown::relative(fp, &c.src);
+        if source != *link {
+            line.push_str(&format!(" · [source]({})", source));
+        }
+        lines.push(line);
+
+        let summary = c.meta.summary.clone().or(markdown::summary(&c.content));
+        if let Some(summary) = summary {
+            lines.push(String::new());
+            lines.push(format!("  {}", summary));
+        }
+
+        let prerequisites: Vec<String> = c
+            .meta
+            .prerequisites
+            .iter()
+            .map(
+                |p| match chapters.iter().zip(links).find(|(c, _)| c.name == *p) {
+                    Some((c, link)) => format!("[{}]({})", c.title, link),
+                    None => p.clone(),
+                },
+            )
+            .collect();
+        if !prerequisites.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("  Prerequisites: {}", prerequisites.join(", ")));
+        }
+
+        if !c.meta.tags.is_empty() {
+            let tags: Vec<String> = c.meta.tags.iter().map(|t| format!("`{}`", t)).collect();
+            lines.push(String::new());
+            lines.push(format!("  Tags: {}", tags.join(" ")));
+        }
+        lines.push(String::new());
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn material_list(fp: &str, material: &[(&str, &str)]) -> Vec<String> {
+    material
+        .iter()
+        .map(|(title, path)| format!("- [{}]({})", title, markdown::relative(fp, path)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::Meta;
+
+    fn chapter(name: &str, src: &str, content: &str, meta: Meta) -> Chapter {
+        Chapter {
+            name: name.to_string(),
+            src: src.to_string(),
+            title: markdown::title(content).unwrap(),
+            content: content.to_string(),
+            lines: Vec::new(),
+            file: format!("{}.md", name),
+            meta,
+        }
+    }
+
+    #[test]
+    fn chapters() {
+        let chapters = [
+            chapter(
+                "traits",
+                "src/chapters/traits.rs",
+                "# Traits\n\nShared\nbehavior.\n",
+                Meta {
+                    difficulty: Some("beginner".to_string()),
+                    tags: vec!["traits".to_string(), "generics".to_string()],
+                    ..Meta::default()
+                },
+            ),
+            chapter(
+                "closures",
+                "docs/closures.md",
+                "# Closures\n",
+                Meta {
+                    summary: Some("Anonymous functions.".to_string()),
+                    prerequisites: vec!["traits".to_string(), "ownership".to_string()],
+                    ..Meta::default()
+                },
+            ),
+        ];
+        let links = ["docs/traits.md".to_string(), "docs/closures.md".to_string()];
+        assert_eq!(
+            chapter_list("./README.md", &chapters, &links),
+            [
+                "- **[Traits](docs/traits.md)** · 1 min read · beginner · [source](src/chapters/traits.rs)",
+                "",
+                "  Shared behavior.",
+                "",
+                "  Tags: `traits` `generics`",
+                "",
+                "- **[Closures](docs/closures.md)** · 1 min read",
+                "",
+                "  Anonymous functions.",
+                "",
+                "  Prerequisites: [Traits](docs/traits.md), ownership",
+            ]
+        );
+    }
+
+    #[test]
+    fn material() {
+        assert_eq!(
+            material_list("docs/README.md", &[("Cheatsheet", "extra/cheatsheet.md")]),
+            ["- [Cheatsheet](../extra/cheatsheet.md)"]
+        );
+    }
+}
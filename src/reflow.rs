@@ -0,0 +1,328 @@
+use crate::config::Config;
+
+// Formatting of the prose of a chapter source:
+//
+// - `//t` paragraphs and list items are reflowed to `fmt.width` columns,
+//   headings, tables, HTML and fenced code are kept as they are;
+// - prose is separated from code by a blank line, code is never touched;
+// - with `fmt.straight_quotes`, typographic quotes are replaced by
+//   straight ones, outside of code.
+pub struct Options {
+    pub width: usize,
+    pub straight_quotes: bool,
+}
+
+impl Options {
+    pub fn from_config(config: &Config) -> Result<Options, String> {
+        Ok(Options {
+            width: config.get_int("fmt.width")?.unwrap_or(80) as usize,
+            straight_quotes: config.get_bool("fmt.straight_quotes")?.unwrap_or(false),
+        })
+    }
+}
+
+const PREFIX: &str = "//t";
+
+pub fn format(src: &str, opts: &Options) -> String {
+    let lines: Vec<&str> = src.lines().collect();
+    let mut out: Vec<String> = Vec::new();
+
+    let mut i = 0;
+    while i < lines.len() {
+        if !is_prose(lines[i]) {
+            let blank = lines[i].trim().is_empty();
+            if !blank && !is_directive(lines[i]) && out.last().is_some_and(|l| is_prose(l)) {
+                out.push(String::new());
+            }
+            out.push(lines[i].to_string());
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < lines.len() && is_prose(lines[i]) {
+            i += 1;
+        }
+        let texts: Vec<String> = lines[start..i]
+            .iter()
+            .map(|l| {
+                let text = &l[PREFIX.len()..];
+                text.strip_prefix(' ')
+                    .unwrap_or(text)
+                    .trim_end()
+                    .to_string()
+            })
+            .collect();
+
+        let block = format_block(&texts, opts);
+        if block.is_empty() {
+            continue;
+        }
+        if out
+            .last()
+            .is_some_and(|l| !l.trim().is_empty() && !is_directive(l))
+        {
+            out.push(String::new());
+        }
+        for text in block {
+            match text.as_str() {
+                "" => out.push(PREFIX.to_string()),
+                text => out.push(format!("{} {}", PREFIX, text)),
+            }
+        }
+    }
+
+    let mut out = out.join("\n");
+    if src.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+// Prose lines at the top level, directives excluded.
+fn is_prose(line: &str) -> bool {
+    line.starts_with(PREFIX) && !is_directive(line)
+}
+
+fn is_directive(line: &str) -> bool {
+    line.starts_with("//t!")
+}
+
+fn format_block(texts: &[String], opts: &Options) -> Vec<String> {
+    // room for the prefix and its space
+    let width = opts.width.saturating_sub(PREFIX.len() + 1);
+    let mut out: Vec<String> = Vec::new();
+    let mut fenced = false;
+
+    let mut i = 0;
+    while i < texts.len() {
+        let text = texts[i].as_str();
+        if text.trim_start().starts_with("```") {
+            fenced = !fenced;
+        }
+        if fenced || text.trim_start().starts_with("```") {
+            out.push(text.to_string());
+            i += 1;
+            continue;
+        }
+
+        if text.trim().is_empty() {
+            if out.last().is_some_and(|l| !l.is_empty()) {
+                out.push(String::new());
+            }
+            i += 1;
+            continue;
+        }
+
+        if is_verbatim(text) {
+            out.push(quotes(text, opts));
+            i += 1;
+            continue;
+        }
+
+        // a paragraph, or a list item with its continuation lines
+        let marker = list_marker(text);
+        let mut words = tokens(&quotes(&text[marker.map_or(0, |m| m.len())..], opts));
+        let mut indent = String::new();
+        let first = i;
+        i += 1;
+        while i < texts.len() {
+            let next = texts[i].as_str();
+            if next.trim().is_empty()
+                || is_verbatim(next)
+                || list_marker(next).is_some()
+                || next.trim_start().starts_with("```")
+            {
+                break;
+            }
+            if i == first + 1 && marker.is_some() {
+                indent = next[..next.len() - next.trim_start().len()].to_string();
+            }
+            words.extend(tokens(&quotes(next, opts)));
+            i += 1;
+        }
+
+        let marker = marker.unwrap_or("");
+        out.extend(fill(&words, marker, &indent, width));
+    }
+
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+    out
+}
+
+// Lines that are never reflowed nor merged with the others.
+fn is_verbatim(text: &str) -> bool {
+    let t = text.trim_start();
+    t.starts_with('#')
+        || t.starts_with('|')
+        || t.starts_with('<')
+        || t.starts_with('>')
+        || text.ends_with('\\')
+}
+
+// List marker at the start of a line, with its trailing space and
+// indentation, e.g. `- `, `  1. ` or `(1) `.
+fn list_marker(text: &str) -> Option<&str> {
+    let t = text.trim_start();
+    let indent = text.len() - t.len();
+    let len = if t.starts_with("- ") || t.starts_with("* ") || t.starts_with("+ ") {
+        2
+    } else if let Some(rest) = t.strip_prefix('(') {
+        // explanations of annotated code
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        match rest[digits..].starts_with(") ") {
+            true if digits > 0 => digits + 3,
+            _ => return None,
+        }
+    } else {
+        let digits = t.chars().take_while(|c| c.is_ascii_digit()).count();
+        match t[digits..].starts_with(". ") {
+            true if digits > 0 => digits + 2,
+            _ => return None,
+        }
+    };
+    Some(&text[..indent + len])
+}
+
+// Words of a text. Inline code and chapter links are never split, so that
+// they stay on a single line.
+fn tokens(text: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    let mut open = false;
+    for word in text.split_whitespace() {
+        match out.last_mut() {
+            Some(last) if open => {
+                last.push(' ');
+                last.push_str(word);
+            }
+            _ => out.push(word.to_string()),
+        }
+        let last = out.last().unwrap();
+        open = last.matches('`').count() % 2 == 1
+            || last.matches("[[").count() > last.matches("]]").count();
+    }
+    out
+}
+
+fn fill(words: &[String], marker: &str, indent: &str, width: usize) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut line = marker.to_string();
+    let mut empty = true;
+    for w in words {
+        let len = line.chars().count() + 1 + w.chars().count();
+        // a line starting with `-`, `#`, ... would change the markdown
+        if !empty && len > width && !starts_block(w) {
+            out.push(line);
+            line = format!("{}{}", indent, w);
+            continue;
+        }
+        if !empty {
+            line.push(' ');
+        }
+        line.push_str(w);
+        empty = false;
+    }
+    out.push(line);
+    out
+}
+
+fn starts_block(word: &str) -> bool {
+    matches!(word, "-" | "*" | "+")
+        || word.starts_with(['#', '>', '|', '<'])
+        || list_marker(&format!("{} ", word)).is_some()
+}
+
+fn quotes(text: &str, opts: &Options) -> String {
+    if !opts.straight_quotes {
+        return text.to_string();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    for (i, part) in text.split('`').enumerate() {
+        if i > 0 {
+            out.push('`');
+        }
+        if i % 2 == 1 {
+            out.push_str(part);
+            continue;
+        }
+        for c in part.chars() {
+            out.push(match c {
+                '‘' | '’' => '\'',
+                '“' | '”' => '"',
+                c => c,
+            });
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(width: usize) -> Options {
+        Options {
+            width,
+            straight_quotes: false,
+        }
+    }
+
+    #[test]
+    fn code_untouched() {
+        let src = "fn a() {  \n\n\n    let x = 1;\t\n}\n\n\n\nfn b() {}\n\n";
+        assert_eq!(format(src, &opts(80)), src);
+        assert_eq!(format("fn a() {}", &opts(80)), "fn a() {}");
+    }
+
+    #[test]
+    fn paragraphs() {
+        let src = "//t # A heading that is long\n//t\n//t\n//t one two three\n//t four `five six`   \n//t seven [[a b]] eight\n";
+        assert_eq!(
+            format(src, &opts(20)),
+            "//t # A heading that is long\n//t\n//t one two three\n//t four `five six`\n//t seven [[a b]]\n//t eight\n"
+        );
+    }
+
+    #[test]
+    fn lists_and_verbatim() {
+        let src = "//t - one two\n//t   three four five\n//t (1) six\n//t | a | b |\n//t ```\n//t    code   \n//t ```\n";
+        assert_eq!(
+            format(src, &opts(16)),
+            "//t - one two\n//t   three four\n//t   five\n//t (1) six\n//t | a | b |\n//t ```\n//t    code\n//t ```\n"
+        );
+    }
+
+    #[test]
+    fn blank_line_between_prose_and_code() {
+        let src = "//t Prose.\nfn a() {}\n//t More.\n//t!code title=\"x\"\nfn b() {}\n";
+        assert_eq!(
+            format(src, &opts(80)),
+            "//t Prose.\n\nfn a() {}\n\n//t More.\n//t!code title=\"x\"\nfn b() {}\n"
+        );
+    }
+
+    #[test]
+    fn straight_quotes() {
+        let opts = Options {
+            width: 80,
+            straight_quotes: true,
+        };
+        assert_eq!(format("//t “it’s” `‘x’`\n", &opts), "//t \"it's\" `‘x’`\n");
+    }
+
+    #[test]
+    fn no_break_before_markdown() {
+        assert_eq!(
+            fill(
+                &["aaaa".to_string(), "-".to_string(), "b".to_string()],
+                "",
+                "",
+                5
+            ),
+            ["aaaa -", "b"]
+        );
+    }
+}
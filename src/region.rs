@@ -0,0 +1,48 @@
+use std::fs;
+
+// Generated content is written between a pair of markers, leaving the
+// rest of the file untouched:
+//
+//     <!-- handbook:begin NAME -->
+//     ...
+//     <!-- handbook:end NAME -->
+pub fn fill(fp: &str, name: &str, lines: &[String]) -> Result<(), String> {
+    let src = fs::read_to_string(fp).map_err(|e| e.to_string())?;
+    let begin = format!("<!-- handbook:begin {} -->", name);
+    let end = format!("<!-- handbook:end {} -->", name);
+
+    let mut out: Vec<&str> = Vec::new();
+    let mut found = false;
+    let mut skipping = false;
+    for line in src.lines() {
+        if skipping {
+            if line.trim() == end {
+                skipping = false;
+                out.extend(lines.iter().map(|l| l.as_str()));
+                out.push(line);
+            }
+            continue;
+        }
+
+        out.push(line);
+        if line.trim() == begin {
+            found = true;
+            skipping = true;
+        }
+    }
+
+    if !found {
+        return Err(format!("{}: missing region `{}`", fp, name));
+    }
+    if skipping {
+        return Err(format!("{}: unterminated region `{}`", fp, name));
+    }
+
+    let mut content = out.join("\n");
+    content.push('\n');
+    if content != src {
+        fs::write(fp, content).map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
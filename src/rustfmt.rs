@@ -0,0 +1,92 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Formats a piece of code with the local `rustfmt`. Code sections are
+// often fragments (statements, expressions), which are formatted as the
+// body of a function. None when rustfmt isn't available or can't parse
+// the code either way.
+pub fn format(code: &[String]) -> Option<Vec<String>> {
+    let src = code.join("\n");
+    if let Some(out) = rustfmt(&src) {
+        return Some(out.lines().map(|l| l.to_string()).collect());
+    }
+
+    unwrap(&rustfmt(&wrap(&src))?)
+}
+
+// Whether the local `rustfmt` can be run.
+pub fn available() -> bool {
+    rustfmt("").is_some()
+}
+
+fn wrap(src: &str) -> String {
+    format!("fn __fragment() {{\n{}\n}}\n", src)
+}
+
+// The body of the formatted function, dedented.
+fn unwrap(out: &str) -> Option<Vec<String>> {
+    let lines: Vec<&str> = out.lines().collect();
+    let body = lines.get(1..lines.len().checked_sub(1)?)?;
+    Some(
+        body.iter()
+            .map(|l| l.strip_prefix("    ").unwrap_or(l).to_string())
+            .collect(),
+    )
+}
+
+fn rustfmt(src: &str) -> Option<String> {
+    let mut child = Command::new(rustfmt_bin())
+        .args(["--edition", "2024", "--emit", "stdout", "--quiet"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    child.stdin.take()?.write_all(src.as_bytes()).ok()?;
+    let out = child.wait_with_output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    String::from_utf8(out.stdout).ok()
+}
+
+fn rustfmt_bin() -> String {
+    std::env::var("RUSTFMT").unwrap_or("rustfmt".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(src: &str) -> Vec<String> {
+        src.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn fragments() {
+        let src = "let x = 1;\nif x > 0 {\n    f(x);\n}";
+        let wrapped = wrap(src);
+        assert_eq!(
+            wrapped,
+            "fn __fragment() {\nlet x = 1;\nif x > 0 {\n    f(x);\n}\n}\n"
+        );
+        let formatted =
+            "fn __fragment() {\n    let x = 1;\n    if x > 0 {\n        f(x);\n    }\n}\n";
+        assert_eq!(unwrap(formatted).unwrap(), lines(src));
+        assert_eq!(unwrap("fn __fragment() {}\n"), None);
+    }
+
+    #[test]
+    fn formatting() {
+        if !available() {
+            return;
+        }
+        assert_eq!(format(&lines("fn a()   {  }")).unwrap(), lines("fn a() {}"));
+        assert_eq!(
+            format(&lines("let x=1;\nf( x );")).unwrap(),
+            lines("let x = 1;\nf(x);")
+        );
+        assert_eq!(format(&lines("fn (")), None);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::config::{self, CONFIG_FILE};
+use crate::discover::{CHAPTERS_DIR, MODULES_FILE};
+use std::fs;
+
+// Creates a new chapter from a compiling skeleton: the source file, its
+// module declaration and its place at the end of the reading order.
+// Returns the path of the source file.
+pub fn new_chapter(name: &str) -> Result<String, String> {
+    let valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        return Err(format!(
+            "invalid chapter name `{}`: use a snake_case module name",
+            name
+        ));
+    }
+
+    let fp = format!("{}/{}.rs", CHAPTERS_DIR, name);
+    if fs::exists(&fp).map_err(|e| e.to_string())? {
+        return Err(format!("{} already exists", fp));
+    }
+
+    let modules =
+        fs::read_to_string(MODULES_FILE).map_err(|e| format!("reading {}: {}", MODULES_FILE, e))?;
+    let config = match fs::read_to_string(CONFIG_FILE) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("reading {}: {}", CONFIG_FILE, e)),
+    };
+    let config = config::append(&config, "chapters.order", name)
+        .map_err(|e| format!("{}: {}", CONFIG_FILE, e))?;
+
+    fs::write(&fp, skeleton(name)).map_err(|e| format!("writing {}: {}", fp, e))?;
+    fs::write(MODULES_FILE, declare(&modules, name))
+        .map_err(|e| format!("writing {}: {}", MODULES_FILE, e))?;
+    fs::write(CONFIG_FILE, config).map_err(|e| format!("writing {}: {}", CONFIG_FILE, e))?;
+    Ok(fp)
+}
+
+// Adds `pub mod <name>;` keeping the declarations sorted, and a test
+// calling the example of the chapter to the tests module, created if
+// needed: the chapter file holds only what is rendered.
+fn declare(modules: &str, name: &str) -> String {
+    let decl = format!("pub mod {};", name);
+    let mut lines: Vec<String> = modules.lines().map(|l| l.to_string()).collect();
+    let last = lines
+        .iter()
+        .rposition(|l| l.starts_with("pub mod "))
+        .map_or(0, |i| i + 1);
+    let idx = lines[..last]
+        .iter()
+        .position(|l| l.starts_with("pub mod ") && l.as_str() > decl.as_str())
+        .unwrap_or(last);
+    lines.insert(idx, decl);
+
+    let test = [
+        "    #[test]".to_string(),
+        format!("    fn {}() {{", name),
+        format!("        super::{0}::ex_{0}();", name),
+        "    }".to_string(),
+    ];
+    match lines.iter().position(|l| l.trim() == "mod tests {") {
+        Some(start) => {
+            let mut end = start
+                + lines[start..]
+                    .iter()
+                    .position(|l| l == "}")
+                    .unwrap_or(lines.len() - start);
+            // a blank line between tests
+            if end > start + 1 {
+                lines.insert(end, String::new());
+                end += 1;
+            }
+            lines.splice(end..end, test);
+        }
+        None => {
+            lines.extend([
+                String::new(),
+                "#[cfg(test)]".to_string(),
+                "mod tests {".to_string(),
+            ]);
+            lines.extend(test);
+            lines.push("}".to_string());
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+// Title, intro prose and a first example. The output assertion on the
+// `println!` is checked by the build, like in every other chapter, and
+// `cargo test` runs the example from the test added next to the module
+// declaration.
+fn skeleton(name: &str) -> String {
+    let mut title = name.replace('_', " ");
+    title[..1].make_ascii_uppercase();
+
+    format!(
+        "//t!meta title: {title}
+//t!meta status: draft
+
+//t # {title}
+//t
+//t Introduce the topic of the chapter here: prose is written in `//t`
+//t comments, code between them is rendered as Rust blocks.
+
+pub fn ex_{name}() {{
+    let n = 1 + 1;
+    println!(\"{{}}\", n); // -> 2
+}}
+"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declarations() {
+        let modules = declare("pub mod closures;\npub mod traits;\n", "lifetimes");
+        assert_eq!(
+            modules,
+            "pub mod closures;\npub mod lifetimes;\npub mod traits;\n\n#[cfg(test)]\nmod tests {\n    #[test]\n    fn lifetimes() {\n        super::lifetimes::ex_lifetimes();\n    }\n}\n"
+        );
+        assert_eq!(
+            declare(&modules, "unsafe_code"),
+            "pub mod closures;\npub mod lifetimes;\npub mod traits;\npub mod unsafe_code;\n\n#[cfg(test)]\nmod tests {\n    #[test]\n    fn lifetimes() {\n        super::lifetimes::ex_lifetimes();\n    }\n\n    #[test]\n    fn unsafe_code() {\n        super::unsafe_code::ex_unsafe_code();\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn skeleton_has_no_tests() {
+        let src = skeleton("lifetimes");
+        assert!(src.contains("//t # Lifetimes\n"));
+        assert!(src.contains("pub fn ex_lifetimes() {"));
+        assert!(!src.contains("#[cfg(test)]"));
+    }
+}
@@ -0,0 +1,433 @@
+use crate::config::Config;
+use std::fs;
+
+// Bundled list of English words, one per line, generated by `compile
+// words` from the Rust documentation (see `wordlist.rs` for its source
+// and license).
+const WORDS: &str = include_str!("words.txt");
+
+// Words of the prose and of the comments in code that aren't in the
+// bundled list nor in the project dictionary (`spell.dictionary`, one
+// word per line, `#` comments). Inline code, links and identifiers such
+// as `snake_case` or `CamelCase` names are skipped.
+#[derive(Debug)]
+pub struct Misspelling {
+    pub file: String,
+    pub line: usize,
+    // 1-based, in characters
+    pub column: usize,
+    pub word: String,
+    pub suggestions: Vec<String>,
+    // the only suggestion a single edit away, if any
+    pub fix: Option<String>,
+}
+
+pub struct Dictionary {
+    // lowercase, sorted
+    words: Vec<String>,
+}
+
+impl Dictionary {
+    pub fn load(config: &Config) -> Result<Dictionary, String> {
+        let mut words = entries(WORDS);
+        if let Some(fp) = config.get_str("spell.dictionary")? {
+            let content = fs::read_to_string(fp).map_err(|e| format!("reading {}: {}", fp, e))?;
+            words.extend(entries(&content));
+        }
+
+        words.sort();
+        words.dedup();
+        Ok(Dictionary { words })
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        let known = |w: &str| self.words.binary_search_by(|x| x.as_str().cmp(w)).is_ok();
+        known(&word) || word.strip_suffix("'s").is_some_and(known)
+    }
+
+    // Closest words, at an edit distance of 1 (2 for longer words), with
+    // their distance.
+    pub fn suggest(&self, word: &str) -> Vec<(usize, String)> {
+        let word = word.to_lowercase();
+        let max = if word.chars().count() > 4 { 2 } else { 1 };
+        let mut found: Vec<(usize, &String)> = self
+            .words
+            .iter()
+            .filter_map(|w| distance(&word, w, max).map(|d| (d, w)))
+            .collect();
+        let first = word.chars().next();
+        found.sort_by_key(|(d, w)| (*d, w.chars().next() != first, w.len().abs_diff(word.len())));
+        found.into_iter().map(|(d, w)| (d, w.clone())).collect()
+    }
+}
+
+// Words of a word list, one per line with `#` comments, lowercase.
+fn entries(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or("").trim())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+pub fn check(fp: &str, content: &str, dict: &Dictionary) -> Result<Vec<Misspelling>, String> {
+    let mut out = Vec::new();
+    let mut fenced = false;
+    for (i, line) in content.lines().enumerate() {
+        if line.trim_start().starts_with("//t!") {
+            continue;
+        }
+        let (offset, text) = match prose(line) {
+            Some((offset, text)) => {
+                if text.trim_start().starts_with("```") {
+                    fenced = !fenced;
+                }
+                if fenced {
+                    continue;
+                }
+                (offset, text)
+            }
+            None => match comment(line) {
+                Some(c) => c,
+                None => continue,
+            },
+        };
+
+        for (column, word) in words(text) {
+            if dict.contains(&word) {
+                continue;
+            }
+            let suggestions = dict.suggest(&word);
+            let fix = match suggestions.as_slice() {
+                [(1, w)] => Some(w.clone()),
+                [(1, w), (next, _), ..] if *next > 1 => Some(w.clone()),
+                _ => None,
+            };
+            out.push(Misspelling {
+                file: fp.to_string(),
+                line: i + 1,
+                column: offset + column + 1,
+                word,
+                suggestions: suggestions.into_iter().take(3).map(|(_, w)| w).collect(),
+                fix,
+            });
+        }
+    }
+
+    Ok(out)
+}
+
+// Replaces the misspellings having a fix that `confirm` accepts, keeping
+// their capitalization: prose is never rewritten without asking. Returns
+// the fixed content, if anything was fixed, and the misspellings left.
+pub fn fix(
+    content: &str,
+    misspellings: Vec<Misspelling>,
+    confirm: &mut dyn FnMut(&Misspelling) -> bool,
+) -> (Option<String>, Vec<Misspelling>) {
+    let mut lines: Vec<Vec<char>> = content.lines().map(|l| l.chars().collect()).collect();
+
+    // asked in reading order, replaced right to left so that columns
+    // stay valid
+    let mut left = Vec::new();
+    let mut fixed = Vec::new();
+    for m in misspellings {
+        match m.fix.is_some() && confirm(&m) {
+            true => fixed.push(m),
+            false => left.push(m),
+        }
+    }
+    if fixed.is_empty() {
+        return (None, left);
+    }
+
+    for m in fixed.iter().rev() {
+        let suggestion = m.fix.as_ref().unwrap();
+
+        let mut replacement: Vec<char> = suggestion.chars().collect();
+        if m.word.starts_with(char::is_uppercase) {
+            replacement[0] = replacement[0].to_ascii_uppercase();
+        }
+        let line = &mut lines[m.line - 1];
+        let start = m.column - 1;
+        line.splice(start..start + m.word.chars().count(), replacement);
+    }
+
+    let mut out: Vec<String> = lines.into_iter().map(|l| l.into_iter().collect()).collect();
+    if content.ends_with('\n') {
+        out.push(String::new());
+    }
+    (Some(out.join("\n")), left)
+}
+
+// Prose of a `//t` line, with its column in the line (in characters).
+fn prose(line: &str) -> Option<(usize, &str)> {
+    let text = line.strip_prefix("//t")?;
+    if text.starts_with('!') {
+        return None;
+    }
+    Some((3, text))
+}
+
+// Text of the comment of a line of code. Commented out code (anything
+// with a `;`, braces or an assignment) and output assertions are skipped.
+fn comment(line: &str) -> Option<(usize, &str)> {
+    let start = line.find("//")?;
+    if line[..start].matches('"').count() % 2 == 1 {
+        return None;
+    }
+
+    let text = line[start..].trim_start_matches('/');
+    let code = [";", "{", "}", " = ", "->", "::", "!("];
+    if code.iter().any(|c| text.contains(c)) {
+        return None;
+    }
+    Some((line[..line.len() - text.len()].chars().count(), text))
+}
+
+// Words of a text, with their column (in characters). Inline code, links
+// targets, HTML tags and identifiers are skipped.
+pub fn words(text: &str) -> Vec<(usize, String)> {
+    let mut chars: Vec<char> = text
+        .chars()
+        .map(|c| if c == '’' { '\'' } else { c })
+        .collect();
+    mask(&mut chars, '`', '`');
+    mask(&mut chars, '<', '>');
+    mask_links(&mut chars);
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() {
+            i += 1;
+        }
+
+        let token: String = chars[start..i].iter().collect();
+        if is_identifier(&token) {
+            continue;
+        }
+
+        // words of the token, e.g. `well-known` or `(see`
+        let mut j = start;
+        while j < i {
+            if !chars[j].is_alphabetic() {
+                j += 1;
+                continue;
+            }
+            let word_start = j;
+            while j < i && (chars[j].is_alphabetic() || chars[j] == '\'') {
+                j += 1;
+            }
+            let word: String = chars[word_start..j].iter().collect();
+            let word = word.trim_end_matches('\'').to_string();
+            // acronyms, and single letters (e.g. type parameters)
+            let acronym = word.chars().all(|c| c.is_uppercase());
+            if !acronym && word.chars().count() > 1 {
+                out.push((word_start, word));
+            }
+        }
+    }
+    out
+}
+
+fn is_identifier(token: &str) -> bool {
+    let token = token.trim_matches(|c: char| !c.is_alphanumeric() && c != '_');
+    let mut chars = token.chars().peekable();
+    let mut camel = false;
+    while let Some(c) = chars.next() {
+        if c.is_lowercase() && chars.peek().is_some_and(|n| n.is_uppercase()) {
+            camel = true;
+        }
+    }
+    camel
+        || token.contains('_')
+        || token.contains("::")
+        || token.contains('.') && !token.ends_with('.')
+        || token.chars().any(|c| c.is_ascii_digit())
+}
+
+// Blanks what's between `open` and `close`, both included.
+fn mask(chars: &mut [char], open: char, close: char) {
+    let mut start: Option<usize> = None;
+    for i in 0..chars.len() {
+        match start {
+            None if chars[i] == open => start = Some(i),
+            Some(s) if chars[i] == close => {
+                chars[s..=i].fill(' ');
+                start = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+// Blanks link targets, `](...)`, and chapter links, `[[...]]`.
+fn mask_links(chars: &mut [char]) {
+    let mut i = 0;
+    while i + 1 < chars.len() {
+        let close = match (chars[i], chars[i + 1]) {
+            (']', '(') => ')',
+            ('[', '[') => ']',
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        let end = chars[i + 2..]
+            .iter()
+            .position(|c| *c == close)
+            .map_or(chars.len(), |p| i + 2 + p + 1);
+        let end = end.min(chars.len());
+        chars[i + 1..end].fill(' ');
+        i = end;
+    }
+}
+
+// Damerau-Levenshtein distance (optimal string alignment), or None when
+// greater than `max`.
+fn distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    Some(d[a.len()][b.len()]).filter(|d| *d <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict(words: &[&str]) -> Dictionary {
+        let mut words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        words.sort();
+        Dictionary { words }
+    }
+
+    #[test]
+    fn bundled_words() {
+        let d = Dictionary::load(&Config::default()).unwrap();
+        for w in [
+            "last",
+            "year",
+            "easily",
+            "the",
+            "Closure",
+            "borrow",
+            "doesn't",
+            "compiler's",
+        ] {
+            assert!(d.contains(w), "{}", w);
+        }
+        assert!(!d.contains("borow"));
+    }
+
+    #[test]
+    fn skipped_text() {
+        let words: Vec<String> = words("See `the_code` [link](http://x.io) <b>bold</b> [[traits#x]] snake_case CamelCase v2 e.g. API T")
+            .into_iter()
+            .map(|(_, w)| w)
+            .collect();
+        assert_eq!(words, ["See", "link", "bold"]);
+    }
+
+    #[test]
+    fn columns() {
+        assert_eq!(
+            words("a well-known (word’s)"),
+            [
+                (2, "well".to_string()),
+                (7, "known".to_string()),
+                (14, "word's".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(
+            comment("    // closures capture"),
+            Some((6, " closures capture"))
+        );
+        assert_eq!(comment("let a = 1; // one"), Some((13, " one")));
+        assert_eq!(comment("    // let a = 1;"), None);
+        assert_eq!(comment("f(); // -> 5"), None);
+        assert_eq!(comment("let s = \"// no\";"), None);
+    }
+
+    #[test]
+    fn suggestions() {
+        let d = dict(&["borrow", "below", "bored", "close", "cols"]);
+        assert_eq!(
+            d.suggest("borow"),
+            [
+                (1, "borrow".to_string()),
+                (2, "below".to_string()),
+                (2, "bored".to_string())
+            ]
+        );
+        assert_eq!(
+            d.suggest("clos"),
+            [(1, "cols".to_string()), (1, "close".to_string())]
+        );
+        assert_eq!(distance("closure", "colsure", 2), Some(1));
+        assert_eq!(distance("year", "near", 0), None);
+    }
+
+    #[test]
+    fn fixes_only_confirmed() {
+        let d = dict(&["the", "borrow", "below", "close", "cols", "checker"]);
+        let src = "//t The borow checker, clos.\n//t Borow below.\n";
+        let found = check("x.rs", src, &d).unwrap();
+        let fixes: Vec<Option<&str>> = found.iter().map(|m| m.fix.as_deref()).collect();
+        assert_eq!(fixes, [Some("borrow"), None, Some("borrow")]);
+
+        // nothing accepted, nothing to write
+        let (fixed, left) = fix(src, found, &mut |_| false);
+        assert_eq!(fixed, None);
+        assert_eq!(left.len(), 3);
+
+        let found = check("x.rs", src, &d).unwrap();
+        let mut asked = Vec::new();
+        let (fixed, left) = fix(src, found, &mut |m| {
+            asked.push(m.line);
+            m.line == 2
+        });
+
+        assert_eq!(asked, [1, 2]);
+        let left: Vec<&str> = left.iter().map(|m| m.word.as_str()).collect();
+        assert_eq!(left, ["borow", "clos"]);
+        assert_eq!(
+            fixed.as_deref(),
+            Some("//t The borow checker, clos.\n//t Borrow below.\n")
+        );
+    }
+}
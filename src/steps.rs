@@ -0,0 +1,230 @@
+use crate::compile::Section;
+
+// An example can be written once and built up step by step. Every step
+// starts with a marker, followed by its prose, and is shown as a block of
+// its own, with the code of the steps before elided as `// ...`:
+//
+//     //t!step
+//     //t First, an `Option` of our own.
+//     enum MyOption<T> { ... }
+//
+//     //t!step
+//     //t Then the method taking the closure.
+//     impl<T> MyOption<T> { ... }
+//
+// Markers can be inside a function too, indented. The code before the
+// first marker and after the steps, as the closing brace of the function,
+// is shown in every block. The steps end at the next prose.
+pub const DIRECTIVE: &str = "//t!step";
+
+pub fn is_marker(line: &str) -> bool {
+    line.trim() == DIRECTIVE
+}
+
+// Splits the code sections with steps into their blocks and prose.
+pub fn extract(sections: Vec<Section>, fp: &str) -> Result<Vec<Section>, String> {
+    let mut out = Vec::new();
+    for s in sections {
+        match s {
+            Section::Code { start, lines, .. } if lines.iter().any(|l| is_marker(l)) => {
+                out.extend(split(start, &lines, fp)?);
+            }
+            s => out.push(s),
+        }
+    }
+    Ok(out)
+}
+
+fn split(start: usize, lines: &[String], fp: &str) -> Result<Vec<Section>, String> {
+    let markers: Vec<usize> = (0..lines.len()).filter(|i| is_marker(&lines[*i])).collect();
+    let first = markers[0];
+    let indent = indentation(&lines[first]);
+    if markers.len() < 2 {
+        return Err(format!(
+            "{}:{}: a build-up needs at least 2 steps",
+            fp,
+            start + first
+        ));
+    }
+    if let Some(m) = markers.iter().find(|m| indentation(&lines[**m]) != indent) {
+        return Err(format!(
+            "{}:{}: steps must be at the same indentation",
+            fp,
+            start + m
+        ));
+    }
+
+    // the steps end at the first line less indented than the markers
+    let end = lines[first..]
+        .iter()
+        .position(|l| !l.trim().is_empty() && indentation(l) < indent)
+        .map_or(lines.len(), |n| first + n);
+    let head = &lines[..first];
+    let tail = &lines[end..];
+
+    let mut out = Vec::new();
+    for (k, m) in markers.iter().enumerate() {
+        let next = markers.get(k + 1).copied().unwrap_or(end);
+        let prose: Vec<String> = lines[m + 1..next]
+            .iter()
+            .map_while(|l| text(l))
+            .map(|t| t.to_string())
+            .collect();
+        let from = m + 1 + prose.len();
+
+        let code = trim(&lines[from..next]);
+        if code.is_empty() {
+            return Err(format!("{}:{}: step without code", fp, start + m));
+        }
+        if !prose.is_empty() {
+            out.push(Section::Comment {
+                start: start + m + 1,
+                lines: prose,
+            });
+        }
+
+        let mut block = head.to_vec();
+        if k > 0 {
+            block.push(format!("{}// ...", " ".repeat(indent)));
+        }
+        block.extend(code.iter().cloned());
+        block.extend(tail.iter().cloned());
+        out.push(Section::Code {
+            start: start + from,
+            lines: block,
+            attrs: Default::default(),
+        });
+    }
+    Ok(out)
+}
+
+// Text of a `//t` prose line of a step, at any indentation.
+fn text(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix("//t")?;
+    match rest.is_empty() || rest.starts_with(' ') {
+        true => Some(rest.strip_prefix(' ').unwrap_or(rest).trim_end()),
+        false => None,
+    }
+}
+
+fn trim(lines: &[String]) -> &[String] {
+    let blank = |l: &String| l.trim().is_empty();
+    let from = lines.iter().position(|l| !blank(l)).unwrap_or(lines.len());
+    let to = lines
+        .iter()
+        .rposition(|l| !blank(l))
+        .map_or(from, |i| i + 1);
+    &lines[from..to]
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(src: &str) -> Vec<String> {
+        src.lines().map(String::from).collect()
+    }
+
+    // (kind of section, first line, lines) of every section.
+    fn summary(sections: &[Section]) -> Vec<(&'static str, usize, Vec<String>)> {
+        sections
+            .iter()
+            .map(|s| match s {
+                Section::Comment { start, lines } => ("comment", *start, lines.clone()),
+                Section::Code { start, lines, .. } => ("code", *start, lines.clone()),
+                _ => panic!("unexpected section {:?}", s),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn top_level_steps() {
+        let src = "//t!step\n//t First.\n\nenum A {}\n\n//t!step\nstruct B;\n";
+        let out = split(10, &lines(src), "x.rs").unwrap();
+        assert_eq!(
+            summary(&out),
+            [
+                ("comment", 11, lines("First.")),
+                ("code", 12, lines("enum A {}")),
+                ("code", 16, lines("// ...\nstruct B;")),
+            ]
+        );
+    }
+
+    #[test]
+    fn steps_in_a_function() {
+        let src = "\
+fn a() {
+    let x = 1;
+    //t!step
+    //t
+    //t Then.
+    let y = x;
+    //t!step
+    let z = y;
+}";
+        let out = split(1, &lines(src), "x.rs").unwrap();
+        assert_eq!(
+            summary(&out),
+            [
+                ("comment", 4, lines("\nThen.")),
+                (
+                    "code",
+                    6,
+                    lines("fn a() {\n    let x = 1;\n    let y = x;\n}")
+                ),
+                (
+                    "code",
+                    8,
+                    lines("fn a() {\n    let x = 1;\n    // ...\n    let z = y;\n}")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_sections_untouched() {
+        let sections = vec![
+            Section::Comment {
+                start: 1,
+                lines: lines("//t!step in prose"),
+            },
+            Section::Code {
+                start: 2,
+                lines: lines("fn a() {}"),
+                attrs: Default::default(),
+            },
+        ];
+        assert_eq!(
+            summary(&extract(sections, "x.rs").unwrap()),
+            [
+                ("comment", 1, lines("//t!step in prose")),
+                ("code", 2, lines("fn a() {}")),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        for (src, err) in [
+            (
+                "//t!step\nfn a() {}",
+                "x.rs:1: a build-up needs at least 2 steps",
+            ),
+            (
+                "fn a() {\n    //t!step\n    f();\n        //t!step\n        g();\n}",
+                "x.rs:4: steps must be at the same indentation",
+            ),
+            (
+                "//t!step\n//t Only prose.\n\n//t!step\nfn a() {}",
+                "x.rs:1: step without code",
+            ),
+        ] {
+            assert_eq!(split(1, &lines(src), "x.rs").unwrap_err(), err, "{}", src);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use crate::spell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// The bundled word list of `compile spell` is generated from the prose of
+// the Rust documentation installed by rustup (`rustup component add
+// rust-docs`), licensed under the MIT or Apache 2.0 license like the Rust
+// project. Books under another license (e.g. the embedded book) are left
+// out. A word is kept when it's found on at least `MIN_PAGES` pages, so
+// that typos are left out. Terms of the handbook, and the few common
+// words the documentation lacks, go in the project dictionary instead.
+pub const WORDS_FILE: &str = "./src/words.txt";
+const BOOKS: &[&str] = &[
+    "book",
+    "reference",
+    "nomicon",
+    "rust-by-example",
+    "edition-guide",
+    "cargo",
+    "rustc",
+    "rustdoc",
+    "std",
+];
+const MIN_PAGES: usize = 2;
+
+// Elements whose content isn't prose.
+const SKIPPED: &[&str] = &["head", "nav", "pre", "code", "script", "style"];
+
+// Regenerates the bundled word list, returning its number of words. It
+// takes effect with the next build.
+pub fn write() -> Result<usize, String> {
+    let list = generate()?;
+    fs::write(WORDS_FILE, &list).map_err(|e| format!("writing {}: {}", WORDS_FILE, e))?;
+    Ok(list.lines().filter(|l| !l.starts_with('#')).count())
+}
+
+// The word list, with a header saying where it comes from.
+fn generate() -> Result<String, String> {
+    let version = rustc(&["--version"])?;
+    let sysroot = rustc(&["--print", "sysroot"])?;
+    let dir = Path::new(&sysroot).join("share/doc/rust/html");
+    if !dir.is_dir() {
+        return Err(format!(
+            "{} not found, install the Rust documentation with `rustup component add rust-docs`",
+            dir.display()
+        ));
+    }
+
+    let mut pages = Vec::new();
+    for book in BOOKS {
+        let mut html = Vec::new();
+        html_files(&dir.join(book), &mut html)?;
+        pages.extend(html.iter().map(|p| page_words(p)));
+    }
+
+    let mut out = vec![
+        format!(
+            "# Generated by `compile words` from the documentation of {}",
+            version
+        ),
+        "# (rust-docs component), under the MIT or Apache 2.0 license.".to_string(),
+    ];
+    out.extend(select(&pages));
+    Ok(out.join("\n") + "\n")
+}
+
+// Words found on enough pages, sorted. Pages are given as their words.
+fn select(pages: &[Vec<String>]) -> Vec<String> {
+    // number of pages of every word
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for w in pages.iter().flatten() {
+        *counts.entry(w).or_default() += 1;
+    }
+
+    let mut out: Vec<String> = counts
+        .into_iter()
+        .filter(|(_, pages)| *pages >= MIN_PAGES)
+        .map(|(w, _)| w.to_string())
+        .collect();
+    out.sort();
+    out
+}
+
+// Words of the prose of a page, lowercase, once each.
+fn page_words(html: &str) -> Vec<String> {
+    let mut words: Vec<String> = spell::words(&text(html))
+        .into_iter()
+        .map(|(_, w)| w.to_lowercase())
+        .collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
+// Text of an HTML page: tags and the elements that aren't prose are
+// blanked, and the entities of punctuation decoded.
+fn text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(i) = rest.find('<') {
+        out.push_str(&rest[..i]);
+        out.push(' ');
+        rest = &rest[i + 1..];
+
+        let name: String = rest
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        let end = rest.find('>').map_or(rest.len(), |e| e + 1);
+        let self_closing = rest[..end].ends_with("/>");
+        rest = &rest[end..];
+
+        if SKIPPED.contains(&name.as_str()) && !self_closing {
+            let close = format!("</{}", name);
+            rest = match rest.find(&close) {
+                Some(c) => &rest[c + close.len()..],
+                None => "",
+            };
+            rest = rest.find('>').map_or("", |e| &rest[e + 1..]);
+        }
+    }
+    out.push_str(rest);
+
+    [
+        ("&nbsp;", " "),
+        ("&amp;", "&"),
+        ("&quot;", "\""),
+        ("&#39;", "'"),
+        ("&#x27;", "'"),
+        ("&rsquo;", "'"),
+        ("&lsquo;", "'"),
+        ("&ldquo;", "\""),
+        ("&rdquo;", "\""),
+        ("&mdash;", " "),
+        ("&ndash;", " "),
+        ("&lt;", " "),
+        ("&gt;", " "),
+    ]
+    .iter()
+    .fold(out, |s, (entity, c)| s.replace(entity, c))
+}
+
+fn rustc(args: &[&str]) -> Result<String, String> {
+    let rustc = std::env::var("RUSTC").unwrap_or("rustc".to_string());
+    let out = Command::new(&rustc)
+        .args(args)
+        .output()
+        .map_err(|e| format!("running {}: {}", rustc, e))?;
+    if !out.status.success() {
+        return Err(format!("{} {}: {}", rustc, args.join(" "), out.status));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+// Contents of the HTML files under a directory.
+fn html_files(dir: &Path, out: &mut Vec<String>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("reading {}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            html_files(&path, out)?;
+        } else if path.extension().is_some_and(|e| e == "html") {
+            out.push(
+                fs::read_to_string(&path)
+                    .map_err(|e| format!("reading {}: {}", path.display(), e))?,
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prose_of_pages() {
+        let html = "<html><head><title>Title</title></head><body>\
+            <nav>Sidebar</nav><p>Some&nbsp;<em>prose</em>, it&rsquo;s \
+            <code>let x</code> fine.</p><pre><code>fn main() {}</code></pre><br/>End</body></html>";
+        assert_eq!(page_words(html), ["end", "fine", "it's", "prose", "some"]);
+    }
+
+    #[test]
+    fn selection() {
+        let page = |words: &str| words.split(' ').map(String::from).collect::<Vec<_>>();
+        let pages = [
+            page("alpha beta"),
+            page("beta gamma"),
+            page("beta typo alpha"),
+        ];
+        // gamma and typo are found on a single page
+        assert_eq!(select(&pages), ["alpha", "beta"]);
+    }
+}